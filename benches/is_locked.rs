@@ -0,0 +1,20 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use pidfile::{CachedChecker, PidFile};
+
+fn is_locked(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("bench.pid");
+    let _pidfile = PidFile::new(&path).unwrap();
+
+    let mut group = c.benchmark_group("is_locked");
+    group.bench_function("uncached", |b| {
+        b.iter(|| PidFile::is_locked(&path).unwrap())
+    });
+
+    let mut checker = CachedChecker::new(&path);
+    group.bench_function("cached", |b| b.iter(|| checker.is_locked().unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, is_locked);
+criterion_main!(benches);
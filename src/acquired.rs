@@ -0,0 +1,133 @@
+//! Acquiring a PID file, treating contention as an ordinary outcome rather than an error.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::{current_pid, PidFile, PidFileError};
+
+/// The outcome of [`PidFile::new_noblock_or_pid`].
+#[derive(Debug)]
+#[must_use]
+pub enum Acquired {
+    /// The PID file was created, and is now held by this process.
+    Locked(PidFile),
+
+    /// The PID file is held by the running process with this PID, which may be this process.
+    HeldBy(libc::pid_t),
+}
+
+impl Acquired {
+    /// The held PID file, if it was created.
+    pub fn pidfile(self) -> Option<PidFile> {
+        match self {
+            Acquired::Locked(pidfile) => Some(pidfile),
+            Acquired::HeldBy(_) => None,
+        }
+    }
+}
+
+/// The PID of the process which holds a PID file, as observed while checking it, when `error`
+/// reports that a PID file is held by another process.
+pub(crate) fn observed_holder(error: &io::Error) -> Option<libc::pid_t> {
+    match error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<PidFileError>())
+    {
+        Some(&PidFileError::InUse { pid, .. }) => Some(pid),
+        _ => None,
+    }
+}
+
+impl PidFile {
+    /// Create a new PID file at the given path, or return the PID of the process which holds it.
+    ///
+    /// This returns `Ok(Err(pid))` when the PID file is held by the running process `pid`,
+    /// which may be this process. The PID is the one which was observed when the PID file was
+    /// checked, and which decided that it is held, rather than one read from the PID file
+    /// afterwards, which may since have changed hands. Other errors, including failures to
+    /// check or create the PID file, are returned as errors.
+    pub fn new_result_with_owner(
+        path: impl Into<PathBuf>,
+    ) -> Result<Result<Self, libc::pid_t>, io::Error> {
+        let error = match PidFile::new(path) {
+            Ok(pidfile) => return Ok(Ok(pidfile)),
+            Err(error) => error,
+        };
+
+        if let Some(pid) = observed_holder(&error) {
+            return Ok(Err(pid));
+        }
+        match error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<PidFileError>())
+        {
+            Some(PidFileError::HeldByThisProcess { .. }) => Ok(Err(current_pid()?)),
+            _ => Err(error),
+        }
+    }
+
+    /// Create a new PID file at the given path, or report the PID of the process which holds it.
+    ///
+    /// This behaves like [`PidFile::new`], and never waits, but a PID file held by a running
+    /// process is reported as [`Acquired::HeldBy`], rather than as an error with
+    /// [`io::ErrorKind::AddrInUse`]. Errors are reserved for failures to check or create
+    /// the PID file. A PID file which is already held by this process is reported with the
+    /// PID of this process. See [`PidFile::new_result_with_owner`] for how the PID is observed.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use pidfile::{Acquired, PidFile};
+    ///
+    /// # fn main() -> Result<(), std::io::Error> {
+    /// match PidFile::new_noblock_or_pid("/var/run/myapp.pid")? {
+    ///     Acquired::Locked(pidfile) => {
+    ///         // Start the application.
+    /// #       drop(pidfile);
+    ///     }
+    ///     Acquired::HeldBy(pid) => println!("myapp is already running as PID {pid}"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_noblock_or_pid(path: impl Into<PathBuf>) -> Result<Acquired, io::Error> {
+        match PidFile::new_result_with_owner(path)? {
+            Ok(pidfile) => Ok(Acquired::Locked(pidfile)),
+            Err(pid) => Ok(Acquired::HeldBy(pid)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_new_noblock_or_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("noblock.pid");
+        let pid = std::process::id() as libc::pid_t;
+
+        let pidfile = PidFile::new_noblock_or_pid(&path)
+            .unwrap()
+            .pidfile()
+            .unwrap();
+        let held = silenced(|| PidFile::new_noblock_or_pid(&path).unwrap());
+        assert!(matches!(held, Acquired::HeldBy(held) if held == pid));
+        drop(pidfile);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, child.id().to_string()).unwrap();
+        let held = silenced(|| PidFile::new_noblock_or_pid(&path).unwrap());
+        assert!(matches!(held, Acquired::HeldBy(held) if held == child.id() as libc::pid_t));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let pidfile = PidFile::new_noblock_or_pid(&path).unwrap().pidfile();
+        assert!(pidfile.is_some());
+    }
+}
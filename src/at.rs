@@ -0,0 +1,398 @@
+//! Creating PID files relative to an open directory with the `*at` family of syscalls.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use crate::builder::found_owner;
+use crate::contents::Contents;
+use crate::mode::{mode_of, set_mode, DEFAULT_MODE};
+use crate::sidecar::{SidecarLock, LOCK_WAIT};
+use crate::{current_pid, reclaim, CheckPolicy, PidFile};
+
+/// Convert a path into a C string for use with libc.
+pub(crate) fn c_path(path: &Path) -> Result<CString, io::Error> {
+    CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "PID file path contains a null byte",
+        )
+    })
+}
+
+/// Open a file relative to `dir` with `openat`.
+fn open_at(
+    dir: BorrowedFd<'_>,
+    path: &Path,
+    flags: libc::c_int,
+    mode: libc::mode_t,
+) -> Result<File, io::Error> {
+    let name = c_path(path)?;
+
+    // SAFETY: `name` is a valid C string, and `dir` is a valid file descriptor for
+    // the duration of this call.
+    #[allow(unsafe_code)]
+    let fd = unsafe {
+        libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            flags | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            libc::c_uint::from(mode),
+        )
+    };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `openat` returned a new file descriptor which nothing else owns.
+    #[allow(unsafe_code)]
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Remove a file relative to `dir` with `unlinkat`.
+pub(crate) fn unlink_at(dir: BorrowedFd<'_>, path: &Path) -> Result<(), io::Error> {
+    let name = c_path(path)?;
+
+    // SAFETY: `name` is a valid C string, and `dir` is a valid file descriptor for
+    // the duration of this call.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), 0) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Rename a file relative to `dir` with `renameat`, replacing anything at `to`.
+fn rename_at(dir: BorrowedFd<'_>, from: &Path, to: &Path) -> Result<(), io::Error> {
+    let from = c_path(from)?;
+    let to = c_path(to)?;
+
+    // SAFETY: `from` and `to` are valid C strings, and `dir` is a valid file descriptor for
+    // the duration of this call.
+    #[allow(unsafe_code)]
+    let result =
+        unsafe { libc::renameat(dir.as_raw_fd(), from.as_ptr(), dir.as_raw_fd(), to.as_ptr()) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Read the contents of a file relative to `dir`.
+pub(crate) fn read_at(dir: BorrowedFd<'_>, path: &Path) -> Result<String, io::Error> {
+    let mut file = open_at(dir, path, libc::O_RDONLY, 0)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Read the contents of a file relative to `dir` as bytes.
+fn read_bytes_at(dir: BorrowedFd<'_>, path: &Path) -> Result<Vec<u8>, io::Error> {
+    let mut file = open_at(dir, path, libc::O_RDONLY, 0)?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The contents of the existing file at `path` relative to `dir`, if there is one.
+fn read_existing_at(dir: BorrowedFd<'_>, path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+    match read_bytes_at(dir, path) {
+        Ok(existing) => Ok(Some(existing)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Get the modification time of a file relative to `dir`.
+pub(crate) fn modified_at(dir: BorrowedFd<'_>, path: &Path) -> Result<SystemTime, io::Error> {
+    open_at(dir, path, libc::O_RDONLY, 0)?
+        .metadata()?
+        .modified()
+}
+
+/// Set the access and modification times of a file to now, with `utimensat`.
+///
+/// `path` is relative to `dir`, or to the working directory when there is no `dir`.
+pub(crate) fn touch_at(dir: Option<BorrowedFd<'_>>, path: &Path) -> Result<(), io::Error> {
+    let name = c_path(path)?;
+    let dir = dir.map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd());
+
+    // SAFETY: `name` is a valid C string, and `dir` is a valid file descriptor (or
+    // `AT_FDCWD`) for the duration of this call. Null times mean "now".
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::utimensat(dir, name.as_ptr(), std::ptr::null(), 0) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Set the access and modification times of an open file to now, with `futimens`.
+pub(crate) fn touch_file(file: &File) -> Result<(), io::Error> {
+    // SAFETY: `file` is a valid file descriptor for the duration of this call. Null times
+    // mean "now".
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::futimens(file.as_raw_fd(), std::ptr::null()) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Atomically replace `path` relative to `dir` with `contents`, by writing `tmp` and renaming it.
+///
+/// The replacement keeps the permissions of the file it replaces.
+pub(crate) fn replace_at(
+    dir: BorrowedFd<'_>,
+    tmp: &Path,
+    path: &Path,
+    contents: &str,
+) -> Result<(), io::Error> {
+    let mode = mode_of(open_at(dir, path, libc::O_RDONLY, 0).and_then(|file| file.metadata()));
+    write_over_at(dir, tmp, path, contents, mode)
+}
+
+/// Write `contents` to `tmp` relative to `dir` with `mode`, and rename it over `path`.
+fn write_over_at(
+    dir: BorrowedFd<'_>,
+    tmp: &Path,
+    path: &Path,
+    contents: &str,
+    mode: u32,
+) -> Result<(), io::Error> {
+    // The temporary file has a predictable name, so never reuse a file which is already there.
+    let create = || {
+        open_at(
+            dir,
+            tmp,
+            libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL,
+            DEFAULT_MODE,
+        )
+    };
+    let mut file = match create() {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            unlink_at(dir, tmp)?;
+            create()?
+        }
+        file => file?,
+    };
+    set_mode(&file, mode)?;
+    file.write_all(contents.as_bytes())?;
+    drop(file);
+
+    if let Err(error) = rename_at(dir, tmp, path) {
+        let _ = unlink_at(dir, tmp);
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Create an anonymous file in `dir` with `O_TMPFILE`, which can later be linked into place.
+#[cfg(target_os = "linux")]
+pub(crate) fn open_tmpfile(dir: &Path) -> Result<File, io::Error> {
+    let name = c_path(dir)?;
+
+    // SAFETY: `name` is a valid C string for the duration of this call.
+    #[allow(unsafe_code)]
+    let fd = unsafe {
+        libc::open(
+            name.as_ptr(),
+            libc::O_TMPFILE | libc::O_RDWR | libc::O_CLOEXEC,
+            libc::c_uint::from(DEFAULT_MODE as libc::mode_t),
+        )
+    };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `open` returned a new file descriptor which nothing else owns.
+    #[allow(unsafe_code)]
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Give an anonymous file created by [`open_tmpfile`] a name at `path`.
+///
+/// This fails with [`io::ErrorKind::AlreadyExists`] if `path` already exists.
+#[cfg(target_os = "linux")]
+pub(crate) fn link_tmpfile(file: &File, path: &Path) -> Result<(), io::Error> {
+    // Linking with `AT_EMPTY_PATH` requires `CAP_DAC_READ_SEARCH`, but linking the file's
+    // entry in `/proc` does not.
+    let from = c_path(Path::new(&format!("/proc/self/fd/{}", file.as_raw_fd())))?;
+    let to = c_path(path)?;
+
+    // SAFETY: `from` and `to` are valid C strings for the duration of this call.
+    #[allow(unsafe_code)]
+    let result = unsafe {
+        libc::linkat(
+            libc::AT_FDCWD,
+            from.as_ptr(),
+            libc::AT_FDCWD,
+            to.as_ptr(),
+            libc::AT_SYMLINK_FOLLOW,
+        )
+    };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+impl PidFile {
+    /// Create a new PID file named `name` in a [`cap_std::fs::Dir`], for applications which
+    /// use capability-based filesystem access.
+    ///
+    /// The PID file is created, read, and removed relative to the directory handle, as with
+    /// [`PidFile::new_at`], so no absolute paths or ambient authority are needed. To keep
+    /// within the capability, `name` must be a plain file name, without any directory
+    /// components, otherwise this returns an error with [`io::ErrorKind::InvalidInput`].
+    ///
+    /// Otherwise, this behaves like [`PidFile::new`].
+    #[cfg(feature = "cap-std")]
+    pub fn new_in_dir(dir: &cap_std::fs::Dir, name: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let name = name.into();
+        let mut components = name.components();
+        let plain = matches!(
+            (components.next(), components.next()),
+            (Some(std::path::Component::Normal(_)), None)
+        );
+        if !plain {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "PID file name {name} must not contain directory components",
+                    name = name.display()
+                ),
+            ));
+        }
+
+        PidFile::new_at(dir.as_fd(), name)
+    }
+
+    /// Create a new PID file for this process, relative to an open directory.
+    ///
+    /// The PID file is created with `openat` and removed with `unlinkat`, relative to
+    /// `dir`, so the directory components of the path are resolved only once, when the
+    /// directory was opened. The final component of `name` is opened with `O_NOFOLLOW`,
+    /// so a symlink at that location is never followed.
+    ///
+    /// The directory file descriptor is duplicated, so the caller retains ownership of
+    /// `dir` and may close it at any time. The duplicate is held by the returned
+    /// [`PidFile`] and closed when it is dropped, after the PID file is removed.
+    ///
+    /// Since the full path to the PID file is not known, PID files created this way are
+    /// not tracked by the in-process registry used by [`PidFile::new`].
+    ///
+    /// Otherwise, this behaves like [`PidFile::new`].
+    pub fn new_at(dir: BorrowedFd<'_>, name: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let path = name.into();
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+        let dir = dir.try_clone_to_owned()?;
+        let pid = current_pid()?;
+        let policy = CheckPolicy::default();
+        let contents = Contents::new(pid).render();
+        let mut tmp = std::ffi::OsString::from(".");
+        tmp.push(path.file_name().unwrap_or_default());
+        tmp.push(format!(".{pid}.tmp"));
+        let tmp = path.with_file_name(tmp);
+
+        let reclaimed = loop {
+            let (found, owner) = found_owner(&path, read_bytes_at(dir.as_fd(), &path), &policy);
+            // As in `PidFile::new`, the stale PID file is only replaced under the lock.
+            let reclaimed = reclaim(&path, owner)?;
+
+            let deadline = Instant::now() + LOCK_WAIT;
+            let lock = SidecarLock::acquire_at(Some(dir.as_fd()), &path, deadline)?;
+            let created = lock.claim(
+                &found,
+                || read_existing_at(dir.as_fd(), &path),
+                || match found {
+                    Some(_) => write_over_at(dir.as_fd(), &tmp, &path, &contents, DEFAULT_MODE),
+                    None => {
+                        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL;
+                        let mut file = open_at(dir.as_fd(), &path, flags, DEFAULT_MODE)?;
+                        set_mode(&file, DEFAULT_MODE)?;
+                        file.write_all(contents.as_bytes())
+                    }
+                },
+            );
+
+            match created {
+                Ok(Some(())) => break reclaimed,
+                Ok(None) => {
+                    crate::diag::trace!(path=%path.display(), "PID file {path} was replaced by another process while it was checked, checking it again", path = path.display());
+                }
+                // Another process created the PID file without the lock, so check it again.
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    crate::diag::trace!(path=%path.display(), "PID file {path} was created by another process, checking it again", path = path.display());
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        crate::diag::trace!(%pid, path=%path.display(), "Locked PID file at {path}", path = path.display());
+
+        let mut pidfile = PidFile::locked(path, pid, None);
+        pidfile.dir = Some(dir);
+        pidfile.reclaimed = reclaimed;
+        Ok(pidfile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_at() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = File::open(tmp.path()).unwrap();
+
+        let pid_file = PidFile::new_at(dir.as_fd(), "pidfile-at.pid").unwrap();
+        drop(dir);
+
+        let path = tmp.path().join("pidfile-at.pid");
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "cap-std")]
+    #[test]
+    fn test_new_in_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir =
+            cap_std::fs::Dir::open_ambient_dir(tmp.path(), cap_std::ambient_authority()).unwrap();
+
+        let pid_file = PidFile::new_in_dir(&dir, "pidfile-cap.pid").unwrap();
+        let path = tmp.path().join("pidfile-cap.pid");
+        assert!(PidFile::is_locked(&path).unwrap());
+        assert_eq!(
+            dir.read_to_string("pidfile-cap.pid").unwrap(),
+            std::process::id().to_string()
+        );
+        drop(pid_file);
+        assert!(!path.exists());
+
+        for name in ["../escape.pid", "/tmp/escape.pid", "sub/escape.pid", ".."] {
+            let error = PidFile::new_in_dir(&dir, name).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{name}");
+        }
+    }
+}
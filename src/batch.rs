@@ -0,0 +1,190 @@
+//! Checking many PID files at once, for fan-out monitoring.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::pidfile::Owner;
+use crate::{info_owner, is_invalid, CheckPolicy, PidFile};
+
+/// The state of a PID file, as reported by [`PidFile::check_many`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LockState {
+    /// There is no PID file.
+    Missing,
+
+    /// The PID file is held by the running process with this PID.
+    Held(libc::pid_t),
+
+    /// The PID file was left behind by a process with this PID, which is no longer running.
+    Stale(libc::pid_t),
+
+    /// The PID file is empty, e.g. because a process crashed while writing it.
+    Empty,
+
+    /// The PID file does not contain a valid PID.
+    Invalid,
+}
+
+impl LockState {
+    /// Whether the PID file is held, with the same semantics as [`PidFile::is_locked`].
+    pub fn is_locked(&self) -> bool {
+        matches!(self, LockState::Held(_))
+    }
+}
+
+impl PidFile {
+    /// Check the state of many PID files at once.
+    ///
+    /// This reads each PID file into a single reused buffer, so it is cheaper than calling
+    /// [`PidFile::is_locked`] for each path when polling many PID files repeatedly. The
+    /// results are in the same order as `paths`, and a failure to check one PID file does
+    /// not affect the others.
+    pub fn check_many(paths: &[&Path]) -> Vec<Result<LockState, io::Error>> {
+        let policy = CheckPolicy::default();
+        let mut buffer = String::new();
+
+        paths
+            .iter()
+            .map(|path| {
+                buffer.clear();
+                check(path, &mut buffer, &policy)
+            })
+            .collect()
+    }
+
+    /// Whether the PID file at this path was left behind by a process which is no longer
+    /// running.
+    ///
+    /// This is the question to ask before cleaning up after a crash. It returns `true` for
+    /// PID files whose owner is dead, and for empty PID files. It returns `false` when there
+    /// is no PID file, when it is held by a running process, and when it doesn't contain a
+    /// valid PID, so that files which may not be PID files at all are left alone. The PID
+    /// file is never modified or removed.
+    pub fn is_stale(path: &Path) -> Result<bool, io::Error> {
+        let state = check(path, &mut String::new(), &CheckPolicy::default())?;
+        Ok(matches!(state, LockState::Stale(_) | LockState::Empty))
+    }
+
+    /// The exit code for a `status` command checking the PID file at this path, following
+    /// the LSB init script conventions.
+    ///
+    /// | Code | Meaning                                      | [`LockState`]                       |
+    /// |------|----------------------------------------------|-------------------------------------|
+    /// | `0`  | The program is running                       | `Held`                              |
+    /// | `1`  | The program is dead, and the PID file exists | `Stale` or `Empty`                  |
+    /// | `3`  | The program is not running                   | `Missing`                           |
+    /// | `4`  | The status is unknown                        | `Invalid`, or the check failed      |
+    ///
+    /// Errors are logged rather than returned, so the result can be passed straight to
+    /// [`std::process::exit`].
+    pub fn status_exit_code(path: &Path) -> i32 {
+        let state = check(path, &mut String::new(), &CheckPolicy::default());
+        match state {
+            Ok(LockState::Held(_)) => 0,
+            Ok(LockState::Stale(_) | LockState::Empty) => 1,
+            Ok(LockState::Missing) => 3,
+            Ok(LockState::Invalid) => 4,
+            Err(error) => {
+                crate::diag::error!(path=%path.display(), "Unable to check PID file {path}: {error}", path = path.display());
+                4
+            }
+        }
+    }
+}
+
+/// Check the state of a single PID file, reading it into `buffer`.
+pub(crate) fn check(
+    path: &Path,
+    buffer: &mut String,
+    policy: &CheckPolicy,
+) -> Result<LockState, io::Error> {
+    let read = File::open(path).and_then(|mut file| file.read_to_string(buffer));
+    match read {
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(LockState::Missing),
+        Err(error) => return Err(error),
+    }
+
+    match info_owner(path, buffer, policy) {
+        Ok(Owner::Current(pid) | Owner::Alive(pid)) => Ok(LockState::Held(pid)),
+        Ok(Owner::Dead(pid) | Owner::Previous(pid) | Owner::Settling(pid, _)) => {
+            Ok(LockState::Stale(pid))
+        }
+        Ok(Owner::Absent) => Ok(LockState::Missing),
+        Ok(Owner::Empty) => Ok(LockState::Empty),
+        Err(error) if is_invalid(&error) => Ok(LockState::Invalid),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_check_many() {
+        let tmp = tempfile::tempdir().unwrap();
+        let held = tmp.path().join("held.pid");
+        let invalid = tmp.path().join("invalid.pid");
+        let missing = tmp.path().join("missing.pid");
+        let directory = tmp.path().join("directory.pid");
+
+        let pid_file = PidFile::new(&held).unwrap();
+        std::fs::write(&invalid, "not a pid").unwrap();
+        std::fs::create_dir(&directory).unwrap();
+
+        let results = silenced(|| PidFile::check_many(&[&held, &invalid, &directory, &missing]));
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &LockState::Held(std::process::id() as libc::pid_t)
+        );
+        assert_eq!(results[1].as_ref().unwrap(), &LockState::Invalid);
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap(), &LockState::Missing);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("stale.pid");
+        assert!(!PidFile::is_stale(&path).unwrap());
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert!(!PidFile::is_stale(&path).unwrap());
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, pid.to_string()).unwrap();
+        assert!(PidFile::is_stale(&path).unwrap());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_status_exit_code() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("status.pid");
+        assert_eq!(PidFile::status_exit_code(&path), 3);
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(PidFile::status_exit_code(&path), 0);
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, pid.to_string()).unwrap();
+        assert_eq!(PidFile::status_exit_code(&path), 1);
+
+        let code = silenced(|| {
+            std::fs::write(&path, "not a pid").unwrap();
+            PidFile::status_exit_code(&path)
+        });
+        assert_eq!(code, 4);
+    }
+}
@@ -0,0 +1,110 @@
+//! The time at which the system was booted, for detecting PID files from before a reboot.
+
+use std::io;
+
+use crate::contents::Contents;
+
+/// How far the boot time may drift between reads before it is treated as a reboot.
+///
+/// The boot time is derived from the current time and the uptime, so it shifts slightly
+/// when the system clock is adjusted.
+const DRIFT: u64 = 60;
+
+/// The time at which the system was booted, in seconds since the UNIX epoch.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn boot_time() -> Result<u64, io::Error> {
+    let stat = std::fs::read_to_string("/proc/stat")?;
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|btime| btime.trim().parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no btime in /proc/stat"))
+}
+
+/// The time at which the system was booted, in seconds since the UNIX epoch.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+))]
+pub(crate) fn boot_time() -> Result<u64, io::Error> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_BOOTTIME];
+    // SAFETY: `timeval` is plain old data, for which all zeroes is a valid value.
+    #[allow(unsafe_code)]
+    let mut boottime: libc::timeval = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::timeval>();
+
+    // SAFETY: `mib`, `boottime` and `len` are valid for the duration of this call, and
+    // `len` is the size of `boottime`.
+    #[allow(unsafe_code)]
+    let result = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as _,
+            (&mut boottime as *mut libc::timeval).cast(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(boottime.tv_sec as u64)
+}
+
+/// The time at which the system was booted, which is unavailable on this platform.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+pub(crate) fn boot_time() -> Result<u64, io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the boot time is unavailable on this platform",
+    ))
+}
+
+/// Whether the boot time recorded in a PID file with these contents is from before the
+/// system was last booted.
+///
+/// PID files without a recorded boot time are never from a previous boot.
+pub(crate) fn previous_boot(contents: &Contents) -> Result<bool, io::Error> {
+    let Some(recorded) = contents.field("boot") else {
+        return Ok(false);
+    };
+
+    let recorded: u64 = recorded
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "expected a boot time"))?;
+    Ok(recorded.saturating_add(DRIFT) < boot_time()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_previous_boot() {
+        let boot = boot_time().unwrap();
+
+        let mut contents = Contents::new(1);
+        assert!(!previous_boot(&contents).unwrap());
+
+        contents.set_field("boot", boot);
+        assert!(!previous_boot(&contents).unwrap());
+
+        contents.set_field("boot", boot - 3600);
+        assert!(previous_boot(&contents).unwrap());
+    }
+}
@@ -0,0 +1,1863 @@
+//! Configuring how PID files are created.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::contents::{to_timestamp, Contents};
+use crate::jitter::Jitter;
+use crate::lost::OnLockLost;
+use crate::mode::{set_mode, DEFAULT_MODE};
+use crate::pidfile::Owner;
+use crate::quiet::{in_use, quietly};
+use crate::registry::Registration;
+use crate::sidecar::{SidecarLock, LOCK_WAIT};
+use crate::{
+    contents_owner, current_pid, hostname, parent_pid, reclaim, CheckPolicy, HealthMode, LockLost,
+    PidFile, RemoteLiveness,
+};
+
+/// How often to retry creating a PID file which is held by another process.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A builder for configuring how a [`PidFile`] is created.
+///
+/// # Example
+///
+/// ```rust
+/// use pidfile::PidFile;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("myapp").join("myapp.pid");
+/// let pidfile = PidFile::builder(&path)
+///     .create_dirs(true)
+///     .remove_created_dirs(true)
+///     .create()?;
+/// # drop(pidfile);
+/// # assert!(!path.parent().unwrap().exists());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    path: PathBuf,
+    create_dirs: bool,
+    remove_created_dirs: bool,
+    record_host: bool,
+    #[cfg(unix)]
+    record_ppid: bool,
+    record_start_time: bool,
+    record_boot_time: bool,
+    lease: Option<Duration>,
+    atomic_create: bool,
+    #[cfg(unix)]
+    fcntl_lock: bool,
+    lock_dir: bool,
+    write_pid: bool,
+    attempts: u32,
+    retry_delay: Duration,
+    jitter: Duration,
+    jitter_seed: Option<u64>,
+    mode: u32,
+    io_timeout: Option<Duration>,
+    #[cfg(unix)]
+    expect_uid: Option<libc::uid_t>,
+    #[cfg(unix)]
+    owner: Option<(libc::uid_t, libc::gid_t)>,
+    on_lock_lost: Option<OnLockLost>,
+    policy: CheckPolicy,
+    /// The deadline of the caller, from [`Builder::create_until`] or [`Builder::io_timeout`].
+    deadline: Option<Instant>,
+}
+
+impl Builder {
+    /// Create a new builder for a PID file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            create_dirs: false,
+            remove_created_dirs: false,
+            record_host: false,
+            #[cfg(unix)]
+            record_ppid: false,
+            record_start_time: false,
+            record_boot_time: false,
+            lease: None,
+            atomic_create: false,
+            #[cfg(unix)]
+            fcntl_lock: false,
+            lock_dir: false,
+            write_pid: true,
+            attempts: 1,
+            retry_delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            jitter_seed: None,
+            mode: DEFAULT_MODE,
+            io_timeout: None,
+            #[cfg(unix)]
+            expect_uid: None,
+            #[cfg(unix)]
+            owner: None,
+            on_lock_lost: None,
+            policy: CheckPolicy::default(),
+            deadline: None,
+        }
+    }
+
+    /// Create any missing parent directories of the PID file.
+    ///
+    /// Defaults to `false`.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Remove the parent directories of the PID file when it is dropped, but only
+    /// if they were created by [`Builder::create_dirs`] and are empty.
+    ///
+    /// Directories which already existed before the PID file was created are never
+    /// removed, nor are directories which contain other files. Defaults to `false`.
+    pub fn remove_created_dirs(mut self, remove_created_dirs: bool) -> Self {
+        self.remove_created_dirs = remove_created_dirs;
+        self
+    }
+
+    /// Record the hostname of this machine in the PID file.
+    ///
+    /// This allows [`PidFile::validate`] to recognize PID files written by other hosts,
+    /// e.g. when the PID file is on shared storage. Defaults to `false`.
+    pub fn record_host(mut self, record_host: bool) -> Self {
+        self.record_host = record_host;
+        self
+    }
+
+    /// Record the PID of this process's parent in the PID file.
+    ///
+    /// This lets tooling map workers to the supervisor which spawned them, and detect
+    /// orphaned workers with [`PidFile::parent_pid`]. Defaults to `false`.
+    #[cfg(unix)]
+    pub fn record_ppid(mut self, record_ppid: bool) -> Self {
+        self.record_ppid = record_ppid;
+        self
+    }
+
+    /// Record the time at which the PID file was locked in the PID file.
+    ///
+    /// [`PidFile::age`] prefers this over the modification time of the PID file, which
+    /// changes whenever the PID file is rewritten (e.g. by [`PidFile::heartbeat`]).
+    /// Defaults to `false`.
+    pub fn record_start_time(mut self, record_start_time: bool) -> Self {
+        self.record_start_time = record_start_time;
+        self
+    }
+
+    /// Record the time at which the system was booted in the PID file.
+    ///
+    /// After a reboot, an unrelated process may be running with the PID recorded in a PID
+    /// file left behind before the reboot. PID files which record a boot time before the
+    /// current one are treated as free, regardless of whether a process with their PID is
+    /// running. This uses `btime` from `/proc/stat` on Linux, and `kern.boottime` on macOS
+    /// and the BSDs. Creating the PID file fails with [`io::ErrorKind::Unsupported`] on
+    /// other platforms. Defaults to `false`.
+    pub fn record_boot_time(mut self, record_boot_time: bool) -> Self {
+        self.record_boot_time = record_boot_time;
+        self
+    }
+
+    /// Hold the PID file as a lease, which expires `lease` after it is created.
+    ///
+    /// Once its lease has expired, the PID file is treated as free, regardless of whether
+    /// the owning process is still running. This is separate from (and takes precedence
+    /// over) checking whether the owner is alive, and helps when the owner may be alive but
+    /// wedged, or on shared storage where the owner is not visible to other hosts. The owner
+    /// must call [`PidFile::renew`] periodically to keep holding the PID file.
+    ///
+    /// Defaults to no lease, so the PID file is held for as long as its owner is running.
+    pub fn lease(mut self, lease: Duration) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Write the PID file in full before it becomes visible at its path.
+    ///
+    /// By default, the PID file is created and then written, so other processes may briefly
+    /// observe an empty PID file. With this option, the PID is written to an anonymous
+    /// `O_TMPFILE` in the same directory, which is then linked into place. Where `O_TMPFILE`
+    /// is unsupported (e.g. on other platforms, or some filesystems), the PID is written to
+    /// a temporary file which is hard linked into place instead. Either way, creation fails
+    /// with [`io::ErrorKind::AddrInUse`] if another process creates the PID file first.
+    ///
+    /// Defaults to `false`.
+    pub fn atomic_create(mut self, atomic_create: bool) -> Self {
+        self.atomic_create = atomic_create;
+        self
+    }
+
+    /// Hold an `fcntl(F_SETLK)` write lock on the whole PID file, for as long as it is held.
+    ///
+    /// Many traditional daemons take `fcntl` record locks on their PID files, so this makes
+    /// the PID file mutually exclusive with them (`flock` locks don't always interoperate
+    /// with `fcntl` locks). Creation fails with [`io::ErrorKind::AddrInUse`] if another
+    /// process holds a conflicting lock. The PID file is kept open, and the lock is released
+    /// when the [`PidFile`] is dropped, after the PID file is removed. The existing contents
+    /// are still checked as usual, in case the owner doesn't use `fcntl` locks.
+    ///
+    /// `fcntl` locks are released when the process closes *any* file descriptor for the
+    /// file, so this process must not open the PID file by any other means while it is
+    /// held, including [`PidFile::is_locked`] and the other functions which read a PID file
+    /// by its path. Methods on the held [`PidFile`] (such as [`PidFile::heartbeat`]) use the
+    /// locked file descriptor, and rewrite the PID file in place rather than atomically.
+    ///
+    /// This can't be combined with [`Builder::create_with_file`], and takes precedence over
+    /// [`Builder::atomic_create`]. Defaults to `false`.
+    #[cfg(unix)]
+    pub fn fcntl_lock(mut self, fcntl_lock: bool) -> Self {
+        self.fcntl_lock = fcntl_lock;
+        self
+    }
+
+    /// Use `remote` to check whether the owners of PID files written on other hosts are alive.
+    ///
+    /// PID files only record their host when created with [`Builder::record_host`]. When an
+    /// existing PID file records a host other than this one, the local process table says
+    /// nothing about its owner, so `remote` is asked instead. Without a resolver, [`UnknownRemote`]
+    /// is used, which never allows PID files from other hosts to be reclaimed.
+    ///
+    /// [`UnknownRemote`]: crate::UnknownRemote
+    pub fn remote_liveness(mut self, remote: impl RemoteLiveness + 'static) -> Self {
+        self.policy.remote = Some(Arc::new(remote));
+        self
+    }
+
+    /// Create the PID file with the permissions `mode`, e.g. `0o640`.
+    ///
+    /// The permissions are set on the PID file as soon as it is created, before the PID is
+    /// written, so they don't depend on the `umask` of the process. Rewriting the PID file,
+    /// e.g. with [`PidFile::heartbeat`], keeps its permissions. This is ignored on platforms
+    /// without Unix permissions.
+    ///
+    /// Defaults to `0o644`, so that monitoring tools running as other users can read the
+    /// PID file.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Require that this process is running with the effective user ID `uid`.
+    ///
+    /// This is checked before anything else, so that running a daemon as the wrong user
+    /// produces a clear error, rather than a confusing [`io::ErrorKind::PermissionDenied`]
+    /// from deep inside the filesystem. A mismatch returns an error with
+    /// [`io::ErrorKind::PermissionDenied`], carrying [`PidFileError::WrongUser`].
+    ///
+    /// [`PidFileError::WrongUser`]: crate::PidFileError::WrongUser
+    #[cfg(unix)]
+    pub fn expect_uid(mut self, uid: libc::uid_t) -> Self {
+        self.expect_uid = Some(uid);
+        self
+    }
+
+    /// Change the owner of the PID file to `uid` and `gid` as soon as it is created, for
+    /// daemons which create their PID file with privileges and then drop them.
+    ///
+    /// Without this, a PID file created by root (e.g. in `/run`) still belongs to root after
+    /// the daemon switches to an unprivileged user, which then can't rewrite it, e.g. with
+    /// [`PidFile::heartbeat`]. With [`Builder::lock_dir`], the lock directory is given to
+    /// `uid` and `gid` as well, so that its contents can be removed.
+    ///
+    /// Changing the owner of a file requires privileges (`CAP_CHOWN` on Linux), so this is
+    /// meant for processes which create the PID file as root. If the owner can't be changed,
+    /// the PID file is removed again, and creating it fails, usually with
+    /// [`io::ErrorKind::PermissionDenied`]. Removing the PID file when it is dropped needs
+    /// write permission on the directory which contains it, so that directory must also be
+    /// writable by `uid`, e.g. `/run/myapp` rather than `/run`.
+    ///
+    /// Rewriting the PID file before dropping privileges replaces it with a file owned by
+    /// root again, so drop privileges first. Defaults to keeping the owner of the process
+    /// which creates the PID file.
+    #[cfg(unix)]
+    pub fn chown(mut self, uid: libc::uid_t, gid: libc::gid_t) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Use `parser` to read the PID from existing PID files, for bespoke lock file formats.
+    ///
+    /// The parser is given the full contents of the PID file, and is used when checking
+    /// whether an existing PID file is still held. Returning an error with
+    /// [`io::ErrorKind::InvalidData`] marks the PID file as invalid, so it is reclaimed,
+    /// while any other error is returned from [`Builder::create`]. Since a custom format
+    /// has no metadata fields, the PID is always checked on this host.
+    ///
+    /// The parser must be pure and fast, since it may run on hot paths. Defaults to the
+    /// built-in parser, which reads a decimal PID from the first line of the file.
+    pub fn pid_parser(mut self, parser: fn(&str) -> Result<libc::pid_t, io::Error>) -> Self {
+        self.policy.parser = Some(parser);
+        self
+    }
+
+    /// Check the health of the owners of existing PID files with `check`, in addition to
+    /// checking whether they are running.
+    ///
+    /// `check` is called with the PID of the owner of an existing PID file on this host, and
+    /// returns whether it is healthy, e.g. by connecting to its health socket. With
+    /// [`HealthMode::Either`], it is only called when the owner is running, and a running
+    /// owner which is unhealthy is treated as dead, so its PID file is reclaimed. With
+    /// [`HealthMode::Both`], it is only called when the owner is not running, and an owner
+    /// which is not running but is healthy is treated as alive, so its PID file is kept.
+    ///
+    /// Errors from `check` are returned from [`Builder::create`], and never cause a PID file
+    /// to be reclaimed, so `check` must return `Ok(false)` only when it is sure that the
+    /// owner is unhealthy. Otherwise, a healthy process which is merely slow to respond
+    /// would lose its PID file. The PID of this process and PID files from other hosts are
+    /// never checked. Defaults to no health check.
+    pub fn health_check(
+        mut self,
+        check: fn(libc::pid_t) -> Result<bool, io::Error>,
+        mode: HealthMode,
+    ) -> Self {
+        self.policy.health = Some((check, mode));
+        self
+    }
+
+    /// Record a stable identity for this process in the PID file, e.g. the application name
+    /// and an instance number, to recognize PID files left by its previous instances.
+    ///
+    /// When an existing PID file whose owner is no longer running records the same instance
+    /// ID, it was left by an unclean shutdown of a previous instance of this process. It is
+    /// reclaimed without waiting for the [`Builder::grace_period`], and the recovery is
+    /// logged at info level. PID files whose owner is still running are never reclaimed,
+    /// even if they record the same instance ID. The ID must not contain a newline, otherwise
+    /// creating the PID file returns an error with [`io::ErrorKind::InvalidInput`]. Defaults
+    /// to no instance ID.
+    pub fn instance_id(mut self, instance_id: impl Into<String>) -> Self {
+        self.policy.instance = Some(instance_id.into().into());
+        self
+    }
+
+    /// Write `magic` into the PID file, and refuse to replace files at the path which don't
+    /// carry it, to guard against a misconfigured path pointing at an unrelated file.
+    ///
+    /// The magic value is written as a `magic` metadata line after the PID, so readers which
+    /// only parse the PID still work. With this option, an existing file which would
+    /// otherwise be reclaimed because it is stale or doesn't contain a valid PID is left
+    /// alone unless it carries the same magic value, and creating the PID file returns an
+    /// error with [`io::ErrorKind::AlreadyExists`], carrying [`PidFileError::Unrecognized`].
+    /// Empty files are still replaced, since a process which crashes while creating its PID
+    /// file leaves one behind. PID files held by a running process are never replaced,
+    /// whether or not they carry the magic value.
+    ///
+    /// After enabling this, PID files left behind by earlier versions of an application
+    /// without the magic value must be removed by hand. The magic value must not contain a
+    /// newline, otherwise creating the PID file returns an error with
+    /// [`io::ErrorKind::InvalidInput`]. Defaults to no magic value, so any stale or invalid
+    /// file is replaced.
+    ///
+    /// [`PidFileError::Unrecognized`]: crate::PidFileError::Unrecognized
+    pub fn magic(mut self, magic: impl Into<String>) -> Self {
+        self.policy.magic = Some(magic.into().into());
+        self
+    }
+
+    /// Only reclaim a stale PID file once it has gone unmodified for at least `grace`.
+    ///
+    /// A PID file whose owner is no longer running, but which was modified within the grace
+    /// period, is treated as held, and creating the PID file returns an error with
+    /// [`io::ErrorKind::AddrInUse`]. Use [`PidFile::settle_remaining`] to find out how long
+    /// to wait. Defaults to no grace period, so stale PID files are reclaimed immediately.
+    pub fn grace_period(mut self, grace: Duration) -> Self {
+        self.policy.grace = Some(grace);
+        self
+    }
+
+    /// Use a lock directory at the path, containing a PID file, rather than a plain PID file.
+    ///
+    /// The lock directory is first assembled under a temporary name next to the path,
+    /// containing a PID file named `pid`, and is then renamed into place. Renaming a
+    /// directory over an existing, non-empty directory fails on virtually every filesystem
+    /// (including NFS), so the lock directory appears atomically, and always with a PID
+    /// inside it. If a lock directory already exists, its PID file is checked as usual,
+    /// and stale or invalid lock directories are removed and replaced.
+    ///
+    /// To check whether the lock is held, pass the path of the PID file inside the lock
+    /// directory to [`PidFile::is_locked`]. The lock directory and its contents are removed
+    /// when the [`PidFile`] is dropped. Defaults to `false`.
+    pub fn lock_dir(mut self, lock_dir: bool) -> Self {
+        self.lock_dir = lock_dir;
+        self
+    }
+
+    /// Write our PID into the PID file, or leave it empty, as a pure marker for exclusion.
+    ///
+    /// Callers which only need mutual exclusion, and not the PID of the holder, can set this
+    /// to `false` to skip writing and checking PIDs altogether, and rely on the lock alone.
+    /// This must be combined with [`Builder::fcntl_lock`] or [`Builder::lock_dir`], and
+    /// creation fails with [`io::ErrorKind::InvalidInput`] otherwise:
+    ///
+    /// - With [`Builder::fcntl_lock`], the file at the path is locked and left empty. The
+    ///   kernel releases the lock when the holder exits, so a marker left behind by a holder
+    ///   which crashed is simply locked again, without checking what it contains.
+    /// - With [`Builder::lock_dir`], an empty directory is created at the path, which fails
+    ///   with [`io::ErrorKind::AddrInUse`] if it already exists. Nothing records who holds it,
+    ///   so a lock directory left behind by a holder which crashed is never reclaimed, and
+    ///   must be removed by hand.
+    ///
+    /// Stale detection by PID doesn't apply to markers, so every process contending for the
+    /// path must use the same mode: [`PidFile::new`] treats an empty PID file as stale, and
+    /// replaces it. [`PidFile::is_locked`] and the other functions which read the PID can't
+    /// tell whether a marker is held, and methods which read our PID back, such as
+    /// [`PidFile::heartbeat`], fail. The options which record metadata are ignored, and
+    /// markers can't be combined with [`Builder::create_with_file`]. Defaults to `true`.
+    pub fn write_pid(mut self, write_pid: bool) -> Self {
+        self.write_pid = write_pid;
+        self
+    }
+
+    /// Call `callback` when this process finds that the PID file was lost to another process.
+    ///
+    /// Losing the PID file means that another process may now be running in its place, so
+    /// the application will usually want to shut down. The loss is detected by maintenance
+    /// calls which check the PID file on disk: [`PidFile::verify_and_repair`] (and
+    /// [`PidFile::relink_if_missing`]) when they report [`Repair::Taken`](crate::Repair::Taken),
+    /// and calls which update the contents, such as [`PidFile::heartbeat`] and
+    /// [`PidFile::renew`], including renewals by [`PidFile::renew_in_background`], when
+    /// they fail with [`io::ErrorKind::AddrInUse`]. The callback is run on the thread which
+    /// made the call, each time the loss is detected, with the path, our PID, and the PID of
+    /// the new owner.
+    ///
+    /// Every detection also emits an error event, with the `path`, `pid` and `owner` fields,
+    /// under the target `pidfile::lost`, which subscribers can filter on to raise an alert
+    /// or count as a metric.
+    pub fn on_lock_lost(mut self, callback: impl Fn(&LockLost) + Send + Sync + 'static) -> Self {
+        self.on_lock_lost = Some(OnLockLost(Arc::new(callback)));
+        self
+    }
+
+    /// Make up to `attempts` attempts to create the PID file, waiting `delay` between them.
+    ///
+    /// While the PID file is held by another [`PidFile`] (in this process or another),
+    /// creating it is retried until `attempts` have been made, after which the error from
+    /// the last attempt is returned, with [`io::ErrorKind::AddrInUse`]. Other errors are
+    /// returned without retrying. Each attempt is a complete, atomic acquisition, so
+    /// retrying never lets two processes hold the PID file at once.
+    ///
+    /// This suits callers who think in attempts rather than time, e.g. where sleeps are
+    /// coarse; see [`Builder::create_timeout`] to wait for a duration instead. Defaults to
+    /// a single attempt, and zero is treated as one.
+    pub fn retry(mut self, attempts: u32, delay: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Add a random delay of up to `jitter` to each wait between attempts to create the PID
+    /// file, so that processes which start at the same moment don't retry in lockstep.
+    ///
+    /// This applies to the waits of [`Builder::retry`], [`Builder::create_until`] and
+    /// [`Builder::create_timeout`]. Each wait is extended by a delay drawn uniformly from
+    /// zero to `jitter`, independently for each wait. A deadline is still respected, so the
+    /// last wait is cut short at the deadline. Delays are drawn from a fast pseudo-random
+    /// generator, seeded differently for each attempt to create the PID file; see
+    /// [`Builder::jitter_seed`] to make them reproducible in tests. Defaults to no jitter.
+    pub fn retry_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Seed the random delays of [`Builder::retry_jitter`], so that every attempt to create
+    /// the PID file with this builder waits for the same sequence of delays.
+    ///
+    /// This is meant for tests. Processes which share a seed retry in lockstep again, so
+    /// don't share one between processes in production. Defaults to a fresh seed for each
+    /// attempt to create the PID file.
+    pub fn jitter_seed(mut self, seed: u64) -> Self {
+        self.jitter_seed = Some(seed);
+        self
+    }
+
+    /// Give up on filesystem operations which take longer than `timeout`, e.g. on network
+    /// filesystems during a server outage.
+    ///
+    /// Creating the PID file (including any retries) runs on a worker thread, and fails with
+    /// [`io::ErrorKind::TimedOut`] if it doesn't finish within `timeout`. Removing the PID
+    /// file when it is dropped is bounded in the same way; if it times out, the error is
+    /// reported like any other failure to remove the PID file. Other methods on the
+    /// [`PidFile`], such as [`PidFile::heartbeat`], are not bounded.
+    ///
+    /// Operations can't be cancelled, so a system call which has timed out may still be in
+    /// flight, and may still complete, after the error is returned. If creating the PID file
+    /// completes after it has timed out, the PID file is removed again. Defaults to no
+    /// timeout.
+    pub fn io_timeout(mut self, timeout: Duration) -> Self {
+        self.io_timeout = Some(timeout);
+        self
+    }
+
+    /// Create the PID file for this process.
+    ///
+    /// See [`PidFile::new`] for details.
+    pub fn create(self) -> Result<PidFile, io::Error> {
+        self.create_inner(false).map(|(pidfile, _)| pidfile)
+    }
+
+    /// Create the PID file for this process, waiting until `deadline` for it to be free.
+    ///
+    /// While the PID file is held by another [`PidFile`] (in this process or another),
+    /// creating it is retried until `deadline`. If it is still held then, this returns the
+    /// error from the last attempt, with [`io::ErrorKind::AddrInUse`]. Other errors are
+    /// returned without waiting. The deadline is absolute, so one deadline can bound several
+    /// operations without recomputing how long is left for each.
+    pub fn create_until(self, deadline: Instant) -> Result<PidFile, io::Error> {
+        self.create_polling(Some(deadline))
+    }
+
+    /// Create the PID file for this process, waiting up to `timeout` for it to be free.
+    ///
+    /// See [`Builder::create_until`] for details.
+    pub fn create_timeout(self, timeout: Duration) -> Result<PidFile, io::Error> {
+        // A timeout too long to represent is as good as waiting forever.
+        self.create_polling(Instant::now().checked_add(timeout))
+    }
+
+    fn create_polling(mut self, deadline: Option<Instant>) -> Result<PidFile, io::Error> {
+        self.deadline = deadline;
+        let mut jitter = self.jitter();
+        let mut attempt = 1;
+        loop {
+            let result = if attempt == 1 {
+                self.clone().create()
+            } else {
+                quietly(|| self.clone().create())
+            };
+
+            match result {
+                Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+                    let remaining = match deadline {
+                        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                        None => POLL_INTERVAL,
+                    };
+                    if remaining.is_zero() {
+                        log_gave_up(&self.path, attempt, &error);
+                        return Err(error);
+                    }
+                    crate::diag::trace!(path=%self.path.display(), "PID file {path} is in use, retrying", path = self.path.display());
+                    attempt += 1;
+                    std::thread::sleep(
+                        remaining.min(POLL_INTERVAL.saturating_add(jitter.next_delay())),
+                    );
+                }
+                result => return log_retried(&self.path, attempt, result),
+            }
+        }
+    }
+
+    /// The error for options which need their own handle on the PID file.
+    fn file_unavailable() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the PID file must stay private to hold an fcntl lock",
+        )
+    }
+
+    /// Create the PID file for this process, and return it along with the open file.
+    ///
+    /// The PID is written on the first line of the file, followed by a newline, and the
+    /// returned [`File`] is positioned just after it. Anything written to the file will
+    /// follow the PID line, so readers will still be able to parse the PID. This is
+    /// useful for storing additional runtime status (such as a socket path or a version)
+    /// alongside the PID, under the same lock.
+    ///
+    /// The file is opened for reading and writing, so it can be truncated back to
+    /// [`Seek::stream_position`](std::io::Seek::stream_position) to rewrite the status.
+    ///
+    /// Methods which atomically rewrite the PID file, such as [`PidFile::heartbeat`],
+    /// replace the file on disk, after which writes to the returned file will no
+    /// longer be visible.
+    pub fn create_with_file(self) -> Result<(PidFile, File), io::Error> {
+        // Closing the returned file, or a duplicate of it, would release the lock.
+        #[cfg(unix)]
+        if self.fcntl_lock {
+            return Err(Self::file_unavailable());
+        }
+        if !self.write_pid {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "a PID file which doesn't record a PID has no file to write to",
+            ));
+        }
+
+        let (pidfile, file) = self.create_inner(true)?;
+        Ok((pidfile, file.ok_or_else(Self::file_unavailable)?))
+    }
+
+    /// Attempt to create the PID file for this process, treating the lock as advisory.
+    ///
+    /// This returns `Ok(None)` when the PID file can't be created because of the environment,
+    /// rather than because another process holds the lock, so that the caller can continue
+    /// running without a lock. The errors which map to `Ok(None)` are:
+    ///
+    /// - [`io::ErrorKind::PermissionDenied`], e.g. when the directory isn't writable.
+    /// - `EROFS`, when the PID file would be on a read-only filesystem.
+    /// - [`io::ErrorKind::NotFound`], when the parent directory doesn't exist.
+    ///
+    /// When the PID file is held by a live process, this still returns an error
+    /// with [`io::ErrorKind::AddrInUse`], as do any other errors.
+    pub fn create_tolerant(self) -> Result<Option<PidFile>, io::Error> {
+        let path = self.path.clone();
+        match self.create() {
+            Ok(pidfile) => Ok(Some(pidfile)),
+            Err(error) if is_environmental(&error) => {
+                crate::diag::warn!(path=%path.display(), "Continuing without PID file {path}: {error}", path = path.display());
+                Ok(None)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn create_inner(mut self, newline: bool) -> Result<(PidFile, Option<File>), io::Error> {
+        let _span = crate::diag::debug_span!("pidfile", path = %self.path.display()).entered();
+
+        if let Some(timeout) = self.io_timeout.take() {
+            self.deadline = [self.deadline, Instant::now().checked_add(timeout)]
+                .into_iter()
+                .flatten()
+                .min();
+            // A PID file created after we have given up is dropped on the worker thread.
+            let (mut pidfile, file) =
+                crate::timeout::with_timeout(timeout, move || self.create_inner(newline))?;
+            pidfile.io_timeout = Some(timeout);
+            return Ok((pidfile, file));
+        }
+
+        #[cfg(unix)]
+        if let Some(expected) = self.expect_uid {
+            // SAFETY: `geteuid` is always successful.
+            #[allow(unsafe_code)]
+            let actual = unsafe { libc::geteuid() };
+            if actual != expected {
+                crate::diag::error!(path=%self.path.display(), "PID File {path} must be created by uid {expected}, not {actual}", path = self.path.display());
+                return Err(crate::PidFileError::WrongUser { expected, actual }.into());
+            }
+        }
+        let created_dirs = if self.create_dirs {
+            create_parent_dirs(&self.path)?
+        } else {
+            Vec::new()
+        };
+
+        match self
+            .acquire_retrying(newline)
+            .map_err(|error| self.missing_directory(error))
+        {
+            Ok((mut pidfile, file)) => {
+                pidfile.on_lock_lost = self.on_lock_lost.clone();
+                if self.remove_created_dirs {
+                    pidfile.created_dirs = created_dirs;
+                }
+                Ok((pidfile, file))
+            }
+            Err(error) => {
+                if self.remove_created_dirs {
+                    remove_created_dirs(&created_dirs);
+                }
+                Err(error)
+            }
+        }
+    }
+
+    /// Report a [`io::ErrorKind::NotFound`] error caused by a missing parent directory as
+    /// [`PidFileError::MissingDirectory`](crate::PidFileError::MissingDirectory), naming it.
+    fn missing_directory(&self, error: io::Error) -> io::Error {
+        if error.kind() != io::ErrorKind::NotFound {
+            return error;
+        }
+
+        match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => {
+                crate::diag::error!(path=%self.path.display(), "Directory for PID file {path} does not exist: {dir}", path = self.path.display(), dir = parent.display());
+                crate::PidFileError::MissingDirectory {
+                    path: parent.display().to_string(),
+                }
+                .into()
+            }
+            _ => error,
+        }
+    }
+
+    /// The random delays to add to waits between attempts, set by [`Builder::retry_jitter`].
+    fn jitter(&self) -> Jitter {
+        Jitter::new(self.jitter, self.jitter_seed)
+    }
+
+    fn acquire_retrying(&self, newline: bool) -> Result<(PidFile, Option<File>), io::Error> {
+        let mut jitter = self.jitter();
+        let mut attempt = 1;
+        loop {
+            let result = if attempt == 1 {
+                self.acquire(newline)
+            } else {
+                quietly(|| self.acquire(newline))
+            };
+
+            match result {
+                Err(error)
+                    if error.kind() == io::ErrorKind::AddrInUse && attempt < self.attempts =>
+                {
+                    crate::diag::trace!(path=%self.path.display(), "PID file {path} is in use, retrying (attempt {attempt} of {attempts})", path = self.path.display(), attempts = self.attempts);
+                    attempt += 1;
+                    std::thread::sleep(self.retry_delay.saturating_add(jitter.next_delay()));
+                }
+                Err(error) if error.kind() == io::ErrorKind::AddrInUse && attempt > 1 => {
+                    log_gave_up(&self.path, attempt, &error);
+                    return Err(error);
+                }
+                result => return log_retried(&self.path, attempt, result),
+            }
+        }
+    }
+
+    fn acquire(&self, newline: bool) -> Result<(PidFile, Option<File>), io::Error> {
+        let registration = Registration::new(&self.path)?;
+
+        let pid = current_pid()?;
+        if !self.write_pid {
+            return self.acquire_marker(pid, registration);
+        }
+
+        let mut contents = Contents::new(pid);
+        if self.record_host {
+            contents.set_field("host", hostname()?);
+        }
+        if let Some(lease) = self.lease {
+            contents.set_field("expires", to_timestamp(SystemTime::now() + lease));
+        }
+        if self.record_start_time {
+            contents.set_field("started", to_timestamp(SystemTime::now()));
+        }
+        if let Some(instance) = &self.policy.instance {
+            if instance.contains('\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "instance ID contains a newline",
+                ));
+            }
+            contents.set_field("instance", instance);
+        }
+        if self.record_boot_time {
+            contents.set_field("boot", crate::boot::boot_time()?);
+        }
+        if let Some(magic) = &self.policy.magic {
+            if magic.contains('\n') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "magic value contains a newline",
+                ));
+            }
+            contents.set_field(crate::pidfile::MAGIC_FIELD, magic);
+        }
+        #[cfg(unix)]
+        if self.record_ppid {
+            contents.set_field("ppid", parent_pid());
+        }
+
+        let mut contents = contents.render();
+        if newline && !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+
+        #[cfg(unix)]
+        if self.fcntl_lock && !self.lock_dir {
+            let (file, reclaimed) =
+                crate::fcntl::create_locked(&self.path, &self.policy, &contents, self.mode)?;
+            crate::diag::trace!(%pid, path=%self.path.display(), "Locked PID file at {path} with fcntl", path = self.path.display());
+
+            let mut pidfile = PidFile::locked(self.path.clone(), pid, Some(registration));
+            // A PID file which we can't hand over is removed again when `pidfile` is dropped.
+            self.set_owner(&file, None)?;
+            pidfile.lock_file = Some(file);
+            pidfile.reclaimed = reclaimed;
+            pidfile.written = contents;
+            return Ok((pidfile, None));
+        }
+
+        let (path, (file, reclaimed), lock_dir) = if self.lock_dir {
+            let (path, created) = create_lock_dir(
+                &self.path,
+                &self.policy,
+                pid,
+                &contents,
+                self.mode,
+                self.lock_deadline(),
+            )?;
+            (path, created, Some(self.path.clone()))
+        } else {
+            let created = self.create_file(pid, &contents)?;
+            (self.path.clone(), created, None)
+        };
+        crate::diag::trace!(%pid, path=%path.display(), "Locked PID file at {path}", path = path.display());
+
+        let mut pidfile = PidFile::locked(path, pid, Some(registration));
+        pidfile.lock_dir = lock_dir;
+        pidfile.reclaimed = reclaimed;
+        pidfile.written = contents;
+        #[cfg(unix)]
+        self.set_owner(&file, pidfile.lock_dir.as_deref())?;
+        Ok((pidfile, Some(file)))
+    }
+
+    /// Acquire an empty marker for [`Builder::write_pid`], which relies on the lock alone.
+    fn acquire_marker(
+        &self,
+        pid: libc::pid_t,
+        registration: Registration,
+    ) -> Result<(PidFile, Option<File>), io::Error> {
+        if self.lock_dir {
+            create_marker_dir(&self.path, self.lock_deadline())?;
+            crate::diag::trace!(%pid, path=%self.path.display(), "Locked empty lock directory at {path}", path = self.path.display());
+
+            let mut pidfile = PidFile::locked(self.path.clone(), pid, Some(registration));
+            pidfile.lock_dir = Some(self.path.clone());
+            #[cfg(unix)]
+            if self.owner.is_some() {
+                self.set_owner(&File::open(&self.path)?, None)?;
+            }
+            return Ok((pidfile, None));
+        }
+
+        #[cfg(unix)]
+        if self.fcntl_lock {
+            let file = crate::fcntl::create_marker(&self.path, self.mode)?;
+            crate::diag::trace!(%pid, path=%self.path.display(), "Locked empty file at {path} with fcntl", path = self.path.display());
+
+            let mut pidfile = PidFile::locked(self.path.clone(), pid, Some(registration));
+            self.set_owner(&file, None)?;
+            pidfile.lock_file = Some(file);
+            return Ok((pidfile, None));
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a PID file which doesn't record a PID needs an fcntl lock or a lock directory",
+        ))
+    }
+
+    /// Give the newly created PID file, and its lock directory, to the owner set with
+    /// [`Builder::chown`].
+    #[cfg(unix)]
+    fn set_owner(&self, file: &File, lock_dir: Option<&Path>) -> Result<(), io::Error> {
+        let Some((uid, gid)) = self.owner else {
+            return Ok(());
+        };
+
+        let result = std::os::unix::fs::fchown(file, Some(uid), Some(gid)).and_then(|()| {
+            lock_dir.map_or(Ok(()), |dir| {
+                std::os::unix::fs::chown(dir, Some(uid), Some(gid))
+            })
+        });
+        if let Err(error) = &result {
+            crate::diag::error!(path=%self.path.display(), "Unable to give PID file {path} to uid {uid} and gid {gid}: {error}", path = self.path.display());
+        }
+        result
+    }
+
+    /// How long to wait for another process to release the lock on the PID file.
+    fn lock_deadline(&self) -> Instant {
+        let wait = Instant::now() + LOCK_WAIT;
+        self.deadline.map_or(wait, |deadline| deadline.min(wait))
+    }
+
+    /// Create the PID file at the path, reclaiming the existing PID file if it is stale.
+    ///
+    /// Returns the new file, along with the PID of the process which left the stale PID file
+    /// behind, if there was one.
+    fn create_file(
+        &self,
+        pid: libc::pid_t,
+        contents: &str,
+    ) -> Result<(File, Option<libc::pid_t>), io::Error> {
+        let path = &self.path;
+        loop {
+            // A single read tells us whether the file exists, without a separate (racy) stat.
+            let (found, owner) = found_owner(path, std::fs::read(path), &self.policy);
+            // Checking the owner may run callbacks, so the stale PID file is only replaced
+            // below, under the lock.
+            let reclaimed = reclaim(path, owner)?;
+
+            let lock = SidecarLock::acquire(path, self.lock_deadline())?;
+            let created = lock.claim(
+                &found,
+                || read_existing(path),
+                || match found {
+                    Some(_) => replace_stale(path, pid, contents, self.mode),
+                    None if self.atomic_create => create_linked(path, pid, contents, self.mode),
+                    None => create_new(path, contents, self.mode),
+                },
+            );
+
+            match created {
+                Ok(Some(file)) => return Ok((file, reclaimed)),
+                Ok(None) => {
+                    crate::diag::trace!(path=%path.display(), "PID file {path} was replaced by another process while it was checked, checking it again", path = path.display());
+                }
+                // Another process created the PID file without the lock, so check it again.
+                // Contention is only ever reported from a check, so the error carries the
+                // PID which the check observed.
+                Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+                    crate::diag::trace!(path=%path.display(), "PID file {path} was created by another process, checking it again", path = path.display());
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// The contents of a PID file read as bytes, which must be UTF-8 to be checked.
+pub(crate) fn utf8(contents: Vec<u8>) -> Result<String, io::Error> {
+    String::from_utf8(contents).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// What was found at the PID file `path`, given the result of reading it, along with its owner.
+pub(crate) fn found_owner(
+    path: &Path,
+    existing: Result<Vec<u8>, io::Error>,
+    policy: &CheckPolicy,
+) -> (Option<Vec<u8>>, Result<Owner, io::Error>) {
+    match existing {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (None, Ok(Owner::Absent)),
+        Ok(existing) => {
+            let owner = contents_owner(path, utf8(existing.clone()), policy);
+            (Some(existing), owner)
+        }
+        Err(error) => (None, Err(error)),
+    }
+}
+
+/// The contents of the existing file at `path`, if there is one.
+pub(crate) fn read_existing(path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+    match std::fs::read(path) {
+        Ok(existing) => Ok(Some(existing)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Log that a PID file was still in use after `attempts` attempts to create it.
+///
+/// Attempts after the first are quiet, so this summarizes them.
+fn log_gave_up(path: &Path, attempts: u32, error: &io::Error) {
+    crate::diag::debug!(path=%path.display(), "PID file {path} is still in use after {attempts} attempts: {error}", path = path.display());
+}
+
+/// Log the outcome of the last of `attempts` attempts to create a PID file, if it was retried.
+fn log_retried<T>(
+    path: &Path,
+    attempts: u32,
+    result: Result<T, io::Error>,
+) -> Result<T, io::Error> {
+    if attempts > 1 {
+        match &result {
+            Ok(_) => {
+                crate::diag::debug!(path=%path.display(), "Created PID file {path} after {attempts} attempts", path = path.display())
+            }
+            Err(error) => {
+                crate::diag::debug!(path=%path.display(), "Unable to create PID file {path} after {attempts} attempts: {error}", path = path.display())
+            }
+        }
+    }
+    result
+}
+
+/// The name of the PID file inside a lock directory.
+pub(crate) const LOCK_DIR_PID_FILE: &str = "pid";
+
+/// Create a PID file at `path` containing `contents`, failing if `path` already exists.
+fn create_new(path: &Path, contents: &str, mode: u32) -> Result<File, io::Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path);
+    match file {
+        Ok(mut file) => {
+            set_mode(&file, mode)?;
+            file.write_all(contents.as_bytes())?;
+            Ok(file)
+        }
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            in_use!(debug, path=%path.display(), "PID file {path} was created by another process", path = path.display());
+            Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("PID File {path} is already in use", path = path.display()),
+            ))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Create an empty lock directory at `dir`, failing if it already exists.
+fn create_marker_dir(dir: &Path, deadline: Instant) -> Result<(), io::Error> {
+    // Under the lock, so that this can't race with replacing a stale lock directory.
+    let _lock = SidecarLock::acquire(dir, deadline)?;
+    match std::fs::create_dir(dir) {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            in_use!(error, path=%dir.display(), "Lock directory {path} is held by another process", path = dir.display());
+            Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("PID File {path} is already in use", path = dir.display()),
+            ))
+        }
+        result => result,
+    }
+}
+
+/// Create a lock directory at `dir`, containing a PID file with `contents`.
+///
+/// Returns the path to the PID file inside the lock directory, and the open PID file.
+///
+/// The lock directory is renamed into place under the lock on `dir`, and a stale lock
+/// directory is only removed under the lock, once it has been checked to be unchanged.
+fn create_lock_dir(
+    dir: &Path,
+    policy: &CheckPolicy,
+    pid: libc::pid_t,
+    contents: &str,
+    mode: u32,
+    deadline: Instant,
+) -> Result<(PathBuf, (File, Option<libc::pid_t>)), io::Error> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(dir.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.tmp"));
+    let tmp = dir.with_file_name(name);
+
+    let _ = std::fs::remove_dir_all(&tmp);
+    std::fs::create_dir(&tmp)?;
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(tmp.join(LOCK_DIR_PID_FILE))?;
+        set_mode(&file, mode)?;
+        file.write_all(contents.as_bytes())?;
+
+        let path = dir.join(LOCK_DIR_PID_FILE);
+        loop {
+            // This fails with the PID it observed if the lock directory is held.
+            let (found, owner) = found_owner(dir, std::fs::read(&path), policy);
+            let reclaimed = reclaim(dir, owner)?;
+
+            let lock = SidecarLock::acquire(dir, deadline)?;
+            let renamed = lock.claim(
+                &found,
+                || read_existing(&path),
+                || {
+                    match found {
+                        Some(_) => std::fs::remove_dir_all(dir)?,
+                        // A lock directory without a PID file is held by a process which
+                        // created it without one, for `Builder::write_pid(false)`.
+                        None if dir.exists() => {
+                            in_use!(error, path=%dir.display(), "Lock directory {path} is held by another process", path = dir.display());
+                            return Err(io::Error::new(
+                                io::ErrorKind::AddrInUse,
+                                format!("PID File {path} is already in use", path = dir.display()),
+                            ));
+                        }
+                        None => {}
+                    }
+                    std::fs::rename(&tmp, dir)
+                },
+            )?;
+
+            match renamed {
+                Some(()) => return Ok((path, (file, reclaimed))),
+                None => {
+                    crate::diag::trace!(path=%dir.display(), "Lock directory {path} was replaced by another process while it was checked, checking it again", path = dir.display());
+                }
+            }
+        }
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(&tmp);
+    }
+    result
+}
+
+/// Create a PID file at `path` containing `contents`, which is never visible partially written.
+///
+/// The file is written while it is anonymous (or has a temporary name), and then linked into
+/// place, which fails if `path` already exists.
+fn create_linked(
+    path: &Path,
+    pid: libc::pid_t,
+    contents: &str,
+    mode: u32,
+) -> Result<File, io::Error> {
+    let in_use = |error: io::Error| {
+        if error.kind() != io::ErrorKind::AlreadyExists {
+            return error;
+        }
+
+        in_use!(debug, path=%path.display(), "PID file {path} was created by another process", path = path.display());
+        io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("PID File {path} is already in use", path = path.display()),
+        )
+    };
+
+    #[cfg(target_os = "linux")]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        match crate::at::open_tmpfile(dir) {
+            Ok(mut file) => {
+                set_mode(&file, mode)?;
+                file.write_all(contents.as_bytes())?;
+                match crate::at::link_tmpfile(&file, path) {
+                    Ok(()) => return Ok(file),
+                    // `/proc` isn't mounted, so fall back to a named temporary file.
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                    Err(error) => return Err(in_use(error)),
+                }
+            }
+            // Filesystems which don't support `O_TMPFILE` report one of these.
+            Err(error)
+                if matches!(
+                    error.raw_os_error(),
+                    Some(libc::EOPNOTSUPP | libc::EISDIR | libc::EINVAL)
+                ) =>
+            {
+                crate::diag::debug!(path=%path.display(), "O_TMPFILE is unsupported for {path}: {error}", path = path.display());
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.tmp"));
+    let tmp = path.with_file_name(name);
+
+    let _ = std::fs::remove_file(&tmp);
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&tmp)?;
+    let result = set_mode(&file, mode)
+        .and_then(|_| file.write_all(contents.as_bytes()))
+        .and_then(|_| std::fs::hard_link(&tmp, path).map_err(in_use));
+    let _ = std::fs::remove_file(&tmp);
+    result.map(|_| file)
+}
+
+/// Replace the stale PID file at `path` with a new one containing `contents`, under the
+/// lock on `path`.
+///
+/// The new PID file is written under a temporary name and renamed over the stale one, so
+/// the stale PID file is never removed by its path, and no other process can find `path`
+/// missing in between.
+fn replace_stale(
+    path: &Path,
+    pid: libc::pid_t,
+    contents: &str,
+    mode: u32,
+) -> Result<File, io::Error> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.tmp"));
+    let tmp = path.with_file_name(name);
+
+    let mut file = crate::mode::create_tmp(&tmp)?;
+    let result = set_mode(&file, mode)
+        .and_then(|_| file.write_all(contents.as_bytes()))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result.map(|_| file)
+}
+
+/// Whether an error creating a PID file is due to the environment, rather than contention.
+fn is_environmental(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound
+    ) || error.raw_os_error() == Some(libc::EROFS)
+}
+
+/// Create the missing parent directories of `path`, returning the directories
+/// which were created, from the outermost to the innermost.
+fn create_parent_dirs(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut missing = Vec::new();
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir.as_os_str().is_empty() || dir.exists() {
+            break;
+        }
+        missing.push(dir);
+        parent = dir.parent();
+    }
+
+    let mut created = Vec::new();
+    for dir in missing.into_iter().rev() {
+        match std::fs::create_dir(dir) {
+            Ok(()) => {
+                crate::diag::debug!(path=%dir.display(), "Created directory {path}", path = dir.display());
+                created.push(dir.to_path_buf());
+            }
+            // Someone else created this directory, so it isn't ours to remove.
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(error) => {
+                crate::diag::error!(path=%dir.display(), "Unable to create directory {path}: {error}", path = dir.display());
+                remove_created_dirs(&created);
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(created)
+}
+
+/// Remove directories created by [`create_parent_dirs`], from the innermost to the outermost,
+/// stopping at the first directory which can't be removed (e.g. because it isn't empty).
+pub(crate) fn remove_created_dirs(dirs: &[PathBuf]) {
+    for dir in dirs.iter().rev() {
+        if let Err(error) = std::fs::remove_dir(dir) {
+            crate::diag::debug!(path=%dir.display(), "Not removing directory {path}: {error}", path = dir.display());
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_remove_created_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a").join("b").join("builder.pid");
+
+        let pid_file = Builder::new(&path)
+            .create_dirs(true)
+            .remove_created_dirs(true)
+            .create()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+        assert!(!tmp.path().join("a").exists());
+        assert!(tmp.path().exists());
+    }
+
+    #[test]
+    fn test_create_with_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-status.pid");
+
+        let (pid_file, mut file) = Builder::new(&path).create_with_file().unwrap();
+        writeln!(file, "socket=/run/myapp.sock").unwrap();
+        drop(file);
+
+        let info = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            info,
+            format!("{}\nsocket=/run/myapp.sock\n", std::process::id())
+        );
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_create_tolerant() {
+        let tmp = tempfile::tempdir().unwrap();
+        let missing = tmp.path().join("missing").join("tolerant.pid");
+        let pid_file = silenced(|| Builder::new(&missing).create_tolerant().unwrap());
+        assert!(pid_file.is_none());
+
+        let path = tmp.path().join("tolerant.pid");
+        let pid_file = Builder::new(&path).create_tolerant().unwrap().unwrap();
+        let error = silenced(|| Builder::new(&path).create_tolerant().unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_lock_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("lock-dir.lock");
+        let pid_path = path.join(LOCK_DIR_PID_FILE);
+
+        let pid_file = Builder::new(&path).lock_dir(true).create().unwrap();
+        assert!(path.is_dir());
+        assert!(PidFile::is_locked(&pid_path).unwrap());
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // Stale lock directories are replaced.
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(&pid_path, "not a pid").unwrap();
+        let pid_file = silenced(|| Builder::new(&path).lock_dir(true).create().unwrap());
+        assert!(PidFile::is_locked(&pid_path).unwrap());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_write_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("marker.lock");
+
+        let error = Builder::new(&path).write_pid(false).create().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+
+        let marker = Builder::new(&path)
+            .lock_dir(true)
+            .write_pid(false)
+            .create()
+            .unwrap();
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 0);
+        drop(marker);
+        assert!(!path.exists());
+
+        // Lock directories left behind are never reclaimed.
+        std::fs::create_dir(&path).unwrap();
+        let error = quietly(|| {
+            Builder::new(&path)
+                .lock_dir(true)
+                .write_pid(false)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn test_pid_parser() {
+        fn parse_worker(info: &str) -> Result<libc::pid_t, io::Error> {
+            info.trim()
+                .strip_prefix("worker:")
+                .and_then(|pid| pid.parse().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "expected a worker"))
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("parser.pid");
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, format!("worker:{}\n", child.id())).unwrap();
+
+        let error = silenced(|| {
+            Builder::new(&path)
+                .pid_parser(parse_worker)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let pid_file = Builder::new(&path)
+            .pid_parser(parse_worker)
+            .create()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_atomic_create() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("atomic.pid");
+
+        let (pid_file, _file) = Builder::new(&path)
+            .atomic_create(true)
+            .create_with_file()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        drop(pid_file);
+        assert!(!path.exists());
+
+        let pid = std::process::id() as libc::pid_t;
+        std::fs::write(&path, "").unwrap();
+        let error = silenced(|| create_linked(&path, pid, "", DEFAULT_MODE).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_remote_liveness() {
+        struct DeadRemote;
+
+        impl RemoteLiveness for DeadRemote {
+            fn is_alive(&self, _host: &str, _pid: libc::pid_t) -> crate::Liveness {
+                crate::Liveness::Dead
+            }
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("remote.pid");
+        std::fs::write(&path, format!("{}\nhost=elsewhere\n", std::process::id())).unwrap();
+
+        let error = silenced(|| Builder::new(&path).create().unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+
+        let pid_file = silenced(|| {
+            Builder::new(&path)
+                .remote_liveness(DeadRemote)
+                .create()
+                .unwrap()
+        });
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_existing_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("existing.pid");
+
+        // Absent
+        let pid_file = Builder::new(&path).create().unwrap();
+        drop(pid_file);
+
+        // Present, but stale
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let child_pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, format!("{child_pid}")).unwrap();
+        let pid_file = silenced(|| Builder::new(&path).create().unwrap());
+        drop(pid_file);
+
+        // Present, but invalid
+        std::fs::write(&path, "not a pid").unwrap();
+        let pid_file = silenced(|| Builder::new(&path).create().unwrap());
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_expect_uid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("uid.pid");
+
+        #[allow(unsafe_code)]
+        let uid = unsafe { libc::geteuid() };
+
+        let error = silenced(|| {
+            Builder::new(&path)
+                .expect_uid(uid + 1)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!path.exists());
+
+        let pid_file = Builder::new(&path).expect_uid(uid).create().unwrap();
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_keep_non_empty_dirs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("a").join("b").join("builder.pid");
+
+        let pid_file = Builder::new(&path)
+            .create_dirs(true)
+            .remove_created_dirs(true)
+            .create()
+            .unwrap();
+        std::fs::write(tmp.path().join("a").join("other"), "").unwrap();
+        drop(pid_file);
+        assert!(!tmp.path().join("a").join("b").exists());
+        assert!(tmp.path().join("a").join("other").exists());
+    }
+
+    #[test]
+    fn test_create_until() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-until.pid");
+        let holder = Builder::new(&path).create().unwrap();
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(100);
+        let error = PidFile::new_until(&path, deadline).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(Instant::now() >= deadline);
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(holder);
+        });
+        let pid_file = PidFile::new_timeout(&path, Duration::from_secs(10)).unwrap();
+        releaser.join().unwrap();
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_retry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-retry.pid");
+        let holder = Builder::new(&path).create().unwrap();
+
+        let error = Builder::new(&path)
+            .retry(3, Duration::from_millis(10))
+            .create()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(holder);
+        });
+        let pid_file = Builder::new(&path)
+            .retry(1000, Duration::from_millis(10))
+            .create()
+            .unwrap();
+        releaser.join().unwrap();
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_retry_jitter() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-jitter.pid");
+        let holder = Builder::new(&path).create().unwrap();
+
+        let start = Instant::now();
+        let error = quietly(|| {
+            PidFile::new_with_retries_and_jitter(&path, 3, Duration::ZERO, Duration::ZERO)
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // The seeded delays are the same for every attempt to create the PID file.
+        let builder = Builder::new(&path)
+            .retry(2, Duration::ZERO)
+            .retry_jitter(Duration::from_millis(40))
+            .jitter_seed(7);
+        let delay = builder.jitter().next_delay();
+        assert_eq!(builder.jitter().next_delay(), delay);
+        let start = Instant::now();
+        let error = quietly(|| builder.create().unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(start.elapsed() >= delay);
+        drop(holder);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sidecar_lock() {
+        use std::sync::OnceLock;
+
+        static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+        // The lock is released while callbacks check the owner, so this can take it.
+        fn unhealthy(_pid: libc::pid_t) -> Result<bool, io::Error> {
+            SidecarLock::acquire(PATH.get().unwrap(), Instant::now()).map(|_| false)
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = PATH.get_or_init(|| tmp.path().join("builder-sidecar.pid"));
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(path, child.id().to_string()).unwrap();
+        let pid_file = Builder::new(path)
+            .health_check(unhealthy, HealthMode::Either)
+            .create()
+            .unwrap();
+        drop(pid_file);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // Waiting for another process to release the lock is bounded by the deadline.
+        let lock = SidecarLock::acquire(path, Instant::now()).unwrap();
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(50);
+        let error = quietly(|| PidFile::new_until(path, deadline).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(start.elapsed() < LOCK_WAIT);
+        drop(lock);
+        drop(PidFile::new(path).unwrap());
+    }
+
+    #[test]
+    fn test_health_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-health.pid");
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, child.id().to_string()).unwrap();
+
+        let error = silenced(|| {
+            Builder::new(&path)
+                .health_check(|_| Ok(true), HealthMode::Either)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let pid_file = Builder::new(&path)
+            .health_check(|_| Ok(false), HealthMode::Either)
+            .create()
+            .unwrap();
+        drop(pid_file);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        std::fs::write(&path, child.id().to_string()).unwrap();
+        let error = silenced(|| {
+            Builder::new(&path)
+                .health_check(|_| Ok(true), HealthMode::Both)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("missing");
+        let path = dir.join("builder.pid");
+
+        let error = silenced(|| PidFile::new(&path).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            error
+                .get_ref()
+                .unwrap()
+                .downcast_ref::<crate::PidFileError>(),
+            Some(&crate::PidFileError::MissingDirectory {
+                path: dir.display().to_string()
+            })
+        );
+        assert!(error.to_string().contains(&dir.display().to_string()));
+    }
+
+    #[test]
+    fn test_instance_id() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("builder-instance.pid");
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, format!("{pid}\ninstance=myapp-1\n")).unwrap();
+
+        // A stranger waits out the grace period, but our own previous instance doesn't.
+        let grace = Duration::from_secs(3600);
+        let error = silenced(|| {
+            Builder::new(&path)
+                .instance_id("myapp-2")
+                .grace_period(grace)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let pid_file = Builder::new(&path)
+            .instance_id("myapp-1")
+            .grace_period(grace)
+            .create()
+            .unwrap();
+        assert_eq!(
+            PidFile::read_field(&path, "instance").unwrap().as_deref(),
+            Some("myapp-1")
+        );
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_replace_stale() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("replace-stale.pid");
+        let pid = std::process::id() as libc::pid_t;
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let stale = child.id().to_string().into_bytes();
+        child.wait().unwrap();
+        std::fs::write(&path, &stale).unwrap();
+
+        // Another process replaced the stale PID file after we checked it, so it is left alone.
+        std::fs::write(&path, "4194000").unwrap();
+        let lock = SidecarLock::acquire(&path, Instant::now()).unwrap();
+        let replaced = lock
+            .claim(
+                &Some(stale.clone()),
+                || read_existing(&path),
+                || replace_stale(&path, pid, "replaced", DEFAULT_MODE),
+            )
+            .unwrap();
+        assert!(replaced.is_none());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "4194000");
+
+        std::fs::write(&path, &stale).unwrap();
+        let lock = SidecarLock::acquire(&path, Instant::now()).unwrap();
+        let replaced = lock
+            .claim(
+                &Some(stale),
+                || read_existing(&path),
+                || replace_stale(&path, pid, "replaced", DEFAULT_MODE),
+            )
+            .unwrap();
+        assert!(replaced.is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "replaced");
+        assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("mode.pid");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(mode(&path), 0o644);
+        drop(pid_file);
+
+        let pid_file = Builder::new(&path).mode(0o600).create().unwrap();
+        assert_eq!(mode(&path), 0o600);
+        pid_file.heartbeat().unwrap();
+        assert_eq!(mode(&path), 0o600);
+        drop(pid_file);
+
+        let pid_file = Builder::new(&path)
+            .mode(0o640)
+            .atomic_create(true)
+            .create()
+            .unwrap();
+        assert_eq!(mode(&path), 0o640);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_magic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("magic.pid");
+        let builder = Builder::new(&path).magic("myapp");
+
+        let pid_file = builder.clone().create().unwrap();
+        let info = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(info, format!("{}\nmagic=myapp\n", std::process::id()));
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+
+        // Unrelated files, and stale PID files without the magic value, are left alone.
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id();
+        child.wait().unwrap();
+        for contents in ["important data".to_owned(), dead.to_string()] {
+            std::fs::write(&path, &contents).unwrap();
+            let error = quietly(|| builder.clone().create().unwrap_err());
+            assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+        }
+
+        // Binary files can't be read as PID files at all, and are left alone too.
+        let binary = [0xff, 0xfe, 0x00, 0x80];
+        std::fs::write(&path, binary).unwrap();
+        let error = quietly(|| builder.clone().create().unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), binary);
+
+        // Stale PID files with the magic value are reclaimed, and without the option, any
+        // stale PID file is.
+        std::fs::write(&path, format!("{dead}\nmagic=myapp\n")).unwrap();
+        drop(quietly(|| builder.clone().create().unwrap()));
+        std::fs::write(&path, dead.to_string()).unwrap();
+        drop(quietly(|| Builder::new(&path).create().unwrap()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chown() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("chown.pid");
+        // SAFETY: `geteuid` and `getegid` are always successful.
+        #[allow(unsafe_code)]
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        // Only root can give files away, so test with another user when running as root.
+        let (owner, group) = if uid == 0 { (65534, 65534) } else { (uid, gid) };
+        for lock_dir in [false, true] {
+            let pid_file = Builder::new(&path)
+                .chown(owner, group)
+                .lock_dir(lock_dir)
+                .create()
+                .unwrap();
+            let metadata = std::fs::metadata(pid_file.path()).unwrap();
+            assert_eq!((metadata.uid(), metadata.gid()), (owner, group));
+            drop(pid_file);
+            assert!(!path.exists());
+        }
+
+        if uid != 0 {
+            let error = quietly(|| Builder::new(&path).chown(0, 0).create().unwrap_err());
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+            assert!(!path.exists());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_io_timeout() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("timeout.pid");
+        let pid_file = Builder::new(&path)
+            .io_timeout(Duration::from_secs(10))
+            .create()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+        assert!(!path.exists());
+
+        // Opening a FIFO blocks until it has a writer, like a hung network filesystem.
+        let fifo = tmp.path().join("hung.pid");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        // SAFETY: `name` is a valid C string for the duration of this call.
+        #[allow(unsafe_code)]
+        let result = unsafe { libc::mkfifo(name.as_ptr(), 0o644) };
+        assert_eq!(result, 0);
+
+        let error = Builder::new(&fifo)
+            .io_timeout(Duration::from_millis(50))
+            .create()
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        // Unblock the worker thread, which is still trying to read the FIFO.
+        drop(File::options().write(true).open(&fifo).unwrap());
+    }
+}
@@ -0,0 +1,196 @@
+//! Cached liveness checks for PID files which are polled frequently.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::pidfile::owner_locked;
+use crate::{info_owner, CheckPolicy};
+
+/// The last observed state of a PID file on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snapshot {
+    modified: SystemTime,
+    len: u64,
+    info: Option<String>,
+}
+
+/// A cached variant of [`PidFile::is_locked`](crate::PidFile::is_locked) for
+/// high-frequency liveness polling.
+///
+/// The checker remembers the contents it last read from the file, along with the
+/// file's modification time and length. As long as those are unchanged, repeated
+/// calls to [`CachedChecker::is_locked`] skip reading the file, and check the
+/// cached contents just as [`PidFile::is_locked`](crate::PidFile::is_locked) would,
+/// including whether the owner is still running.
+///
+/// Change detection relies on the file's modification time, so a file which is
+/// rewritten with a different PID of the same length within the resolution of the
+/// filesystem's timestamps will not be noticed until [`CachedChecker::invalidate`]
+/// is called.
+///
+/// # Example
+///
+/// ```rust
+/// use pidfile::{CachedChecker, PidFile};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("cached.pid");
+/// let pidfile = PidFile::new(&path)?;
+/// let mut checker = CachedChecker::new(&path);
+/// assert!(checker.is_locked()?);
+/// assert!(checker.is_locked()?);
+/// # drop(pidfile);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedChecker {
+    path: PathBuf,
+    snapshot: Option<Snapshot>,
+}
+
+impl CachedChecker {
+    /// Create a new cached checker for the PID file at the given path.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            snapshot: None,
+        }
+    }
+
+    /// The path to the PID file being checked.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Forget the cached PID, so that the next check re-reads the file.
+    pub fn invalidate(&mut self) {
+        self.snapshot = None;
+    }
+
+    /// Check if the PID file is in use, re-reading it only when it has changed.
+    ///
+    /// This has the same semantics as [`PidFile::is_locked`](crate::PidFile::is_locked).
+    pub fn is_locked(&mut self) -> Result<bool, io::Error> {
+        let metadata = match std::fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.snapshot = None;
+                return Ok(false);
+            }
+            Err(error) => {
+                crate::diag::error!(path=%self.path.display(), "Unable to check PID file {path}: {error}", path=self.path.display());
+                return Err(error);
+            }
+        };
+
+        let modified = metadata.modified()?;
+        let len = metadata.len();
+
+        let unchanged = self
+            .snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.modified == modified && snapshot.len == len);
+        if !unchanged {
+            let info = self.read()?;
+            self.snapshot = Some(Snapshot {
+                modified,
+                len,
+                info,
+            });
+        }
+
+        match self
+            .snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.info.as_deref())
+        {
+            Some(info) => owner_locked(
+                &self.path,
+                info_owner(&self.path, info, &CheckPolicy::default()),
+            ),
+            None => Ok(false),
+        }
+    }
+
+    /// Read the contents of the file, returning `None` if it isn't UTF-8 or is missing.
+    fn read(&self) -> Result<Option<String>, io::Error> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(info) => Ok(Some(info)),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                crate::diag::warn!(path=%self.path.display(), "Invalid PID file at {path}", path = self.path.display());
+                Ok(None)
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => {
+                crate::diag::error!(path=%self.path.display(), "Unable to check PID file {path}: {error}", path=self.path.display());
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+    use crate::PidFile;
+
+    #[test]
+    fn test_cached_checker() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("cached-test.pid");
+
+        let mut checker = CachedChecker::new(&path);
+        assert!(!checker.is_locked().unwrap());
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert!(checker.is_locked().unwrap());
+        assert!(checker.snapshot.is_some());
+        assert!(checker.is_locked().unwrap());
+
+        drop(pid_file);
+        assert!(!checker.is_locked().unwrap());
+        assert!(checker.snapshot.is_none());
+    }
+
+    #[test]
+    fn test_cached_checker_expired_lease() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("cached-expired.pid");
+
+        // The owner is still running, but its lease has expired.
+        let expired = crate::contents::to_timestamp(SystemTime::UNIX_EPOCH);
+        std::fs::write(
+            &path,
+            format!("{}\nexpires={expired}\n", std::process::id()),
+        )
+        .unwrap();
+
+        let mut checker = CachedChecker::new(&path);
+        assert!(!PidFile::is_locked(&path).unwrap());
+        assert!(!checker.is_locked().unwrap());
+        assert!(!checker.is_locked().unwrap());
+    }
+
+    #[test]
+    fn test_cached_checker_invalid_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("cached-invalid.pid");
+        std::fs::write(&path, "not a pid").unwrap();
+
+        let mut checker = CachedChecker::new(&path);
+        silenced(|| {
+            assert!(!checker.is_locked().unwrap());
+        });
+        assert_eq!(
+            checker
+                .snapshot
+                .and_then(|snapshot| snapshot.info)
+                .as_deref(),
+            Some("not a pid")
+        );
+    }
+}
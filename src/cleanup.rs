@@ -0,0 +1,247 @@
+//! Removing PID files when the process is terminated by `SIGINT` or `SIGTERM`.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The signals which trigger cleanup.
+const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+/// The maximum number of PID files which can be removed by the signal handler.
+const SLOTS: usize = 64;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_PATH: AtomicPtr<libc::c_char> = AtomicPtr::new(ptr::null_mut());
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NO_ACTION: AtomicPtr<libc::sigaction> = AtomicPtr::new(ptr::null_mut());
+
+/// The paths of the PID files to remove, published for use by the signal handler.
+///
+/// The handler can't take locks or allocate, so it reads these slots directly.
+static PATHS: [AtomicPtr<libc::c_char>; SLOTS] = [NO_PATH; SLOTS];
+
+/// The signal handlers which were installed before ours, indexed like [`SIGNALS`].
+static PREVIOUS: [AtomicPtr<libc::sigaction>; 2] = [NO_ACTION; 2];
+
+/// The number of signal handlers currently reading [`PATHS`] or [`PREVIOUS`].
+///
+/// Memory referenced from either is only freed once no handler is reading it.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a [`SignalCleanup`] is currently installed.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Wait until no signal handler is reading the published paths or previous handlers.
+fn quiesce() {
+    while ACTIVE.load(Ordering::SeqCst) > 0 {
+        std::hint::spin_loop();
+    }
+}
+
+/// A PID file path published to the signal handler, which is withdrawn when this is dropped.
+#[derive(Debug)]
+pub(crate) struct Published {
+    slot: usize,
+}
+
+/// Publish the path of a PID file, so that the signal handler removes it.
+///
+/// Returns `None` if the path can't be published, in which case it is not removed on signal.
+pub(crate) fn publish(path: &Path) -> Option<Published> {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return None;
+    };
+    let path = path.into_raw();
+
+    for (slot, entry) in PATHS.iter().enumerate() {
+        if entry
+            .compare_exchange(ptr::null_mut(), path, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            return Some(Published { slot });
+        }
+    }
+
+    crate::diag::warn!("Too many PID files to remove on signal, at most {SLOTS} are supported");
+    // SAFETY: `path` came from `CString::into_raw`, and was never published.
+    #[allow(unsafe_code)]
+    drop(unsafe { CString::from_raw(path) });
+    None
+}
+
+impl Drop for Published {
+    fn drop(&mut self) {
+        let path = PATHS[self.slot].swap(ptr::null_mut(), Ordering::SeqCst);
+        quiesce();
+        // SAFETY: `path` came from `CString::into_raw` in `publish`, and is no longer
+        // published, nor read by any signal handler.
+        #[allow(unsafe_code)]
+        drop(unsafe { CString::from_raw(path) });
+    }
+}
+
+/// Remove the published PID files.
+fn unlink_published() {
+    for entry in &PATHS {
+        let path = entry.load(Ordering::SeqCst);
+        if !path.is_null() {
+            // SAFETY: `path` is a valid C string until it is withdrawn, which waits for us.
+            #[allow(unsafe_code)]
+            unsafe {
+                libc::unlink(path);
+            }
+        }
+    }
+}
+
+/// The signal handler, which restores the previous disposition of the signal and re-raises
+/// it, removing the published PID files first if that will terminate the process.
+extern "C" fn handle(signal: libc::c_int) {
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+
+    let previous = SIGNALS
+        .iter()
+        .position(|&s| s == signal)
+        .map_or(ptr::null_mut(), |index| {
+            PREVIOUS[index].load(Ordering::SeqCst)
+        });
+
+    // SAFETY: `previous` is a valid `sigaction` until it is freed, which waits for us. The
+    // signal is blocked while this handler runs, so the re-raised signal is delivered to the
+    // previous handler (or default action) once we return.
+    #[allow(unsafe_code)]
+    unsafe {
+        // A previous handler may keep the process running, e.g. to shut down gracefully, so
+        // the PID files are only removed when the signal will terminate the process. Without
+        // a previous disposition (while the guard is being dropped), that is the default.
+        if previous.is_null() {
+            unlink_published();
+            libc::signal(signal, libc::SIG_DFL);
+        } else {
+            if (*previous).sa_sigaction == libc::SIG_DFL {
+                unlink_published();
+            }
+            libc::sigaction(signal, previous, ptr::null_mut());
+        }
+        libc::raise(signal);
+    }
+
+    ACTIVE.fetch_sub(1, Ordering::SeqCst);
+}
+
+/// Remove PID files held by this process when it receives `SIGINT` or `SIGTERM`.
+///
+/// Daemons often exit from their signal handlers without running destructors, which
+/// leaves their PID files behind. While this guard is alive, `SIGINT` and `SIGTERM` are
+/// handled by restoring the previously installed handlers and re-raising the signal, so
+/// that the previous handler (or the default action, termination) proceeds as it would
+/// have otherwise. When the previous disposition is the default action, the PID files
+/// held by this process are removed first. A previous handler or `SIG_IGN` may keep the
+/// process running, so then the PID files are left alone, and later signals go straight
+/// to the previous disposition.
+///
+/// Only PID files registered with this crate are removed, i.e. those created with
+/// [`PidFile::new`](crate::PidFile::new) or a [`Builder`](crate::Builder). PID files
+/// created with [`PidFile::new_at`](crate::PidFile::new_at) and lock directories created
+/// with [`Builder::lock_dir`](crate::Builder::lock_dir) are not removed. At most 64 PID
+/// files are removed.
+///
+/// The previous handlers are restored when this guard is dropped. Only one guard can be
+/// installed at a time.
+///
+/// # Example
+///
+/// ```rust
+/// use pidfile::{PidFile, SignalCleanup};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("cleanup.pid");
+/// let _cleanup = SignalCleanup::install()?;
+/// let pidfile = PidFile::new(&path)?;
+/// # drop(pidfile);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SignalCleanup {
+    _private: (),
+}
+
+impl SignalCleanup {
+    /// Install the `SIGINT` and `SIGTERM` handlers.
+    ///
+    /// Returns an error with [`io::ErrorKind::AlreadyExists`] if another [`SignalCleanup`]
+    /// is already installed.
+    pub fn install() -> Result<Self, io::Error> {
+        if INSTALLED.swap(true, Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "signal cleanup is already installed",
+            ));
+        }
+
+        let cleanup = Self { _private: () };
+        for (index, &signal) in SIGNALS.iter().enumerate() {
+            // SAFETY: `sigaction` is plain old data, for which all zeroes is a valid value.
+            #[allow(unsafe_code)]
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+            // Publish the previous action before installing our handler, so the handler
+            // always finds it. `sigaction` fills it in as it installs our handler.
+            let previous = Box::into_raw(Box::new(action));
+            PREVIOUS[index].store(previous, Ordering::SeqCst);
+
+            // SAFETY: `action` and `previous` are valid for the duration of this call.
+            #[allow(unsafe_code)]
+            let result = unsafe {
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, previous)
+            };
+
+            if result != 0 {
+                let error = io::Error::last_os_error();
+                PREVIOUS[index].store(ptr::null_mut(), Ordering::SeqCst);
+                // SAFETY: `previous` came from `Box::into_raw`, and our handler was never
+                // installed, so nothing else can be reading it.
+                #[allow(unsafe_code)]
+                drop(unsafe { Box::from_raw(previous) });
+                drop(cleanup);
+                return Err(error);
+            }
+        }
+
+        crate::diag::debug!("Installed signal handlers to remove PID files");
+        Ok(cleanup)
+    }
+}
+
+impl Drop for SignalCleanup {
+    fn drop(&mut self) {
+        for (index, &signal) in SIGNALS.iter().enumerate() {
+            let previous = PREVIOUS[index].swap(ptr::null_mut(), Ordering::SeqCst);
+            if previous.is_null() {
+                continue;
+            }
+
+            // SAFETY: `previous` is a valid `sigaction`, which was filled in by `install`.
+            #[allow(unsafe_code)]
+            unsafe {
+                libc::sigaction(signal, previous, ptr::null_mut());
+            }
+
+            quiesce();
+            // SAFETY: `previous` came from `Box::into_raw` in `install`, and is no longer
+            // published, nor read by any signal handler.
+            #[allow(unsafe_code)]
+            drop(unsafe { Box::from_raw(previous) });
+        }
+
+        INSTALLED.store(false, Ordering::SeqCst);
+    }
+}
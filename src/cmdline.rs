@@ -0,0 +1,99 @@
+//! Reading the command line of the process which owns a PID file, for diagnostics.
+
+use std::io;
+use std::path::Path;
+
+use crate::{pid_file_owner, CheckPolicy, PidFile};
+
+/// Read the command line of the process with the given PID.
+#[cfg(target_os = "linux")]
+fn command_line(pid: libc::pid_t) -> Option<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+
+    // Kernel threads and zombies have an empty command line.
+    if cmdline.is_empty() {
+        return None;
+    }
+
+    Some(
+        cmdline
+            .strip_suffix(b"\0")
+            .unwrap_or(&cmdline)
+            .split(|&byte| byte == 0)
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect(),
+    )
+}
+
+/// Read the executable path of the process with the given PID.
+///
+/// The full argument vector is not readily available here, so this is only the executable.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn command_line(pid: libc::pid_t) -> Option<Vec<String>> {
+    let mut buffer = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+
+    // SAFETY: `buffer` is valid for writes of its whole length.
+    #[allow(unsafe_code)]
+    let len = unsafe { libc::proc_pidpath(pid, buffer.as_mut_ptr().cast(), buffer.len() as u32) };
+
+    if len <= 0 {
+        return None;
+    }
+
+    buffer.truncate(len as usize);
+    Some(vec![String::from_utf8_lossy(&buffer).into_owned()])
+}
+
+/// Reading command lines is not supported on this platform.
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+fn command_line(_pid: libc::pid_t) -> Option<Vec<String>> {
+    None
+}
+
+impl PidFile {
+    /// Read the command line of the process which holds the PID file at this path.
+    ///
+    /// This is useful for diagnostics when a PID file is already held, e.g. to tell the
+    /// user which program is already running. On Linux, this is the full argument vector
+    /// from `/proc/<pid>/cmdline`. On macOS, only the path to the executable is available.
+    ///
+    /// Returns `Ok(None)` when there is no PID file, when its owner is no longer running,
+    /// or when the command line is unavailable (e.g. the owner belongs to another user on
+    /// a system which hides it) or unsupported on this platform.
+    pub fn owner_command_line(path: &Path) -> Result<Option<Vec<String>>, io::Error> {
+        let owner = pid_file_owner(path, &CheckPolicy::default())?;
+        Ok(owner.alive().and_then(command_line))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_owner_command_line() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-cmdline.pid");
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, format!("{}", child.id())).unwrap();
+
+        // The command line is briefly empty while the child is still being exec'd.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let command = loop {
+            match PidFile::owner_command_line(&path).unwrap() {
+                Some(command) => break command,
+                None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                None => panic!("no command line for PID {}", child.id()),
+            }
+        };
+        assert_eq!(command, ["sleep", "30"]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(PidFile::owner_command_line(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,234 @@
+//! The on-disk format of PID files.
+//!
+//! A PID file always starts with the PID of the owning process on its first line.
+//! The basic format is just that PID, with no trailing newline. Optional metadata is
+//! stored on the following lines as `key=value` pairs, which readers that only
+//! understand the basic format will ignore.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::PidFileError;
+
+/// Parse the contents of a PID file into a PID.
+///
+/// Only the first line of the file is considered. A leading UTF-8 byte order mark
+/// and CRLF line endings, as written by some Windows tools, are ignored.
+pub(crate) fn parse_pid(path: &Path, info: &str) -> Result<libc::pid_t, io::Error> {
+    // Trimming also removes any trailing CRLF, since `\r` is whitespace.
+    let info = info.strip_prefix('\u{feff}').unwrap_or(info).trim_start();
+    let line = info.lines().next().unwrap_or_default();
+    let pid: libc::pid_t = line.trim().parse().map_err(|error| {
+        crate::diag::debug!(path=%path.display(), "Unable to parse PID file {path}: {error}", path = path.display());
+        PidFileError::InvalidPid(error)
+    })?;
+
+    // `kill` treats zero and negative PIDs as process groups, so they can never be checked.
+    if pid <= 0 {
+        crate::diag::debug!(path=%path.display(), "PID file {path} contains a non-positive PID: {pid}", path = path.display());
+        return Err(PidFileError::NonPositivePid(pid).into());
+    }
+
+    Ok(pid)
+}
+
+/// The parsed contents of a PID file: a PID, followed by any additional lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Contents {
+    pid: libc::pid_t,
+    lines: Vec<String>,
+}
+
+impl Contents {
+    /// Contents in the basic format, containing only a PID.
+    pub(crate) fn new(pid: libc::pid_t) -> Self {
+        Self {
+            pid,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Parse the contents of a PID file.
+    pub(crate) fn parse(path: &Path, info: &str) -> Result<Self, io::Error> {
+        let pid = parse_pid(path, info)?;
+        let lines = info
+            .trim_start()
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_owned())
+            .collect();
+        Ok(Self { pid, lines })
+    }
+
+    /// The PID stored in the file.
+    pub(crate) fn pid(&self) -> libc::pid_t {
+        self.pid
+    }
+
+    /// Replace the PID stored in the file, keeping the metadata.
+    pub(crate) fn set_pid(&mut self, pid: libc::pid_t) {
+        self.pid = pid;
+    }
+
+    /// Get the value of a `key=value` metadata line.
+    pub(crate) fn field(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim())
+        })
+    }
+
+    /// All `key=value` metadata lines, in order. Other lines are skipped.
+    pub(crate) fn fields(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lines.iter().filter_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            Some((k.trim(), v.trim()))
+        })
+    }
+
+    /// Set the value of a `key=value` metadata line, replacing any existing value.
+    pub(crate) fn set_field(&mut self, key: &str, value: impl fmt::Display) {
+        let line = format!("{key}={value}");
+        let existing = self
+            .lines
+            .iter_mut()
+            .find(|line| line.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+
+        match existing {
+            Some(existing) => *existing = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    /// Remove a `key=value` metadata line, if there is one.
+    pub(crate) fn remove_field(&mut self, key: &str) {
+        self.lines
+            .retain(|line| !line.split_once('=').is_some_and(|(k, _)| k.trim() == key));
+    }
+
+    /// Render the contents in the on-disk format.
+    pub(crate) fn render(&self) -> String {
+        if self.lines.is_empty() {
+            return format!("{}", self.pid);
+        }
+
+        let mut rendered = format!("{}\n", self.pid);
+        for line in &self.lines {
+            rendered.push_str(line);
+            rendered.push('\n');
+        }
+        rendered
+    }
+}
+
+/// Format a timestamp as whole seconds since the UNIX epoch, for use as a metadata value.
+pub(crate) fn to_timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+}
+
+/// Parse a timestamp stored as seconds since the UNIX epoch.
+pub(crate) fn from_timestamp(value: &str) -> Result<SystemTime, io::Error> {
+    let seconds: u64 = value
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "expected a timestamp"))?;
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pid_windows() {
+        let path = Path::new("windows.pid");
+        assert_eq!(parse_pid(path, "\u{feff}1234").unwrap(), 1234);
+        assert_eq!(parse_pid(path, "1234\r\n").unwrap(), 1234);
+        assert_eq!(parse_pid(path, "\u{feff}1234\r\n").unwrap(), 1234);
+    }
+
+    #[test]
+    fn test_parse_pid_out_of_range() {
+        fn message(info: &str) -> String {
+            let error = parse_pid(Path::new("range.pid"), info).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            error.to_string()
+        }
+
+        let too_large = (libc::pid_t::MAX as i64 + 1).to_string();
+        let too_small = (libc::pid_t::MIN as i64 - 1).to_string();
+        assert!(message(&too_large).contains("out of range"));
+        assert!(message(&too_small).contains("out of range"));
+        assert_eq!(message("-42"), "expected a positive PID, got -42");
+        assert_eq!(message("garbage"), "expected a PID");
+    }
+
+    #[test]
+    fn test_contents_fields() {
+        let path = Path::new("fields.pid");
+        let mut contents = Contents::new(1234);
+        assert_eq!(contents.render(), "1234");
+
+        contents.set_field("heartbeat", 10);
+        contents.set_field("heartbeat", 20);
+        assert_eq!(contents.render(), "1234\nheartbeat=20\n");
+
+        let parsed = Contents::parse(path, &contents.render()).unwrap();
+        assert_eq!(parsed, contents);
+        assert_eq!(parsed.field("heartbeat"), Some("20"));
+        assert_eq!(parse_pid(path, &contents.render()).unwrap(), 1234);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("round-trip.pid");
+
+        for pid in [
+            1,
+            2,
+            9,
+            10,
+            99,
+            100,
+            4096,
+            32767,
+            32768,
+            4194304,
+            libc::pid_t::MAX,
+        ] {
+            let mut contents = Contents::new(pid);
+            for field in [None, Some(("heartbeat", "10")), Some(("host", "example"))] {
+                if let Some((key, value)) = field {
+                    contents.set_field(key, value);
+                }
+
+                std::fs::write(&path, contents.render()).unwrap();
+                let info = std::fs::read_to_string(&path).unwrap();
+                assert_eq!(parse_pid(&path, &info).unwrap(), pid);
+                assert_eq!(Contents::parse(&path, &info).unwrap(), contents);
+            }
+        }
+
+        // PID files written by this crate, with every metadata option.
+        std::fs::remove_file(&path).unwrap();
+        let pid_file = crate::Builder::new(&path)
+            .record_host(true)
+            .record_ppid(true)
+            .record_start_time(true)
+            .record_boot_time(true)
+            .lease(Duration::from_secs(3600))
+            .create()
+            .unwrap();
+        let info = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            parse_pid(&path, &info).unwrap(),
+            crate::current_pid().unwrap()
+        );
+        drop(pid_file);
+    }
+}
@@ -0,0 +1,340 @@
+//! Human-readable descriptions of PID files, for command line `status` output.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::batch::check;
+use crate::contents::{from_timestamp, Contents};
+use crate::{CheckPolicy, LockState, PidFile};
+
+/// Format a duration coarsely, in its largest whole unit, e.g. `3h`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// The status of a PID file, for command line `status` output.
+///
+/// This separates the status of a PID file from its presentation: it is displayed as the
+/// line produced by [`PidFile::describe`], and with the `status` feature it can be serialized with `serde`,
+/// e.g. as JSON for a `--json` flag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "status",
+    derive(serde::Serialize),
+    serde(tag = "state", rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Status {
+    /// The PID file is held by a running process.
+    Running {
+        /// The PID of the owner.
+        pid: libc::pid_t,
+
+        /// The host of the owner, when recorded with
+        /// [`Builder::record_host`](crate::Builder::record_host).
+        #[cfg_attr(feature = "status", serde(skip_serializing_if = "Option::is_none"))]
+        host: Option<String>,
+
+        /// How long the owner has held the PID file, when recorded with
+        /// [`Builder::record_start_time`](crate::Builder::record_start_time).
+        #[cfg_attr(
+            feature = "status",
+            serde(
+                rename = "uptime_secs",
+                serialize_with = "serialize_secs",
+                skip_serializing_if = "Option::is_none"
+            )
+        )]
+        uptime: Option<Duration>,
+    },
+
+    /// The PID file was left behind by a process which is no longer running.
+    Stale {
+        /// The PID of the former owner.
+        pid: libc::pid_t,
+    },
+
+    /// There is no PID file.
+    Free,
+
+    /// The PID file is empty, e.g. because a process crashed while writing it.
+    Empty,
+
+    /// The PID file does not contain a valid PID.
+    Invalid,
+}
+
+/// Serialize an optional duration as whole seconds.
+#[cfg(feature = "status")]
+fn serialize_secs<S: serde::Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_some(&duration.as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Running { pid, host, uptime } => {
+                write!(f, "running (pid {pid}")?;
+                if let Some(host) = host {
+                    write!(f, ", host {host}")?;
+                }
+                if let Some(uptime) = uptime {
+                    write!(f, ", up {}", format_duration(*uptime))?;
+                }
+                write!(f, ")")
+            }
+            Status::Stale { pid } => write!(f, "stale lock from pid {pid}"),
+            Status::Free => write!(f, "not running"),
+            Status::Empty => write!(f, "empty PID file"),
+            Status::Invalid => write!(f, "invalid PID file"),
+        }
+    }
+}
+
+/// Get the status of the PID file at `path`.
+fn status(path: &Path) -> Result<Status, io::Error> {
+    let mut buffer = String::new();
+    let pid = match check(path, &mut buffer, &CheckPolicy::default())? {
+        LockState::Held(pid) => pid,
+        LockState::Stale(pid) => return Ok(Status::Stale { pid }),
+        LockState::Missing => return Ok(Status::Free),
+        LockState::Empty => return Ok(Status::Empty),
+        LockState::Invalid => return Ok(Status::Invalid),
+    };
+
+    let contents = Contents::parse(path, &buffer).ok();
+    let field = |key| contents.as_ref().and_then(|contents| contents.field(key));
+    let uptime = field("started")
+        .and_then(|started| from_timestamp(started).ok())
+        .map(|started| {
+            SystemTime::now()
+                .duration_since(started)
+                .unwrap_or(Duration::ZERO)
+        });
+
+    Ok(Status::Running {
+        pid,
+        host: field("host").map(str::to_owned),
+        uptime,
+    })
+}
+
+/// The status of a PID file, in the schema produced by [`PidFile::status_json`].
+#[cfg(feature = "json")]
+#[derive(Debug, serde::Serialize)]
+struct JsonStatus<'a> {
+    state: &'static str,
+    pid: Option<libc::pid_t>,
+    alive: bool,
+    stale: bool,
+    hostname: Option<&'a str>,
+    age_secs: Option<u64>,
+}
+
+/// Get the status of the PID file at `path` as compact JSON.
+#[cfg(feature = "json")]
+fn status_json(path: &Path) -> Result<String, io::Error> {
+    let mut buffer = String::new();
+    let state = check(path, &mut buffer, &CheckPolicy::default())?;
+    let (name, pid) = match state {
+        LockState::Held(pid) => ("running", Some(pid)),
+        LockState::Stale(pid) => ("stale", Some(pid)),
+        LockState::Missing => ("free", None),
+        LockState::Empty => ("empty", None),
+        LockState::Invalid => ("invalid", None),
+    };
+
+    let contents = Contents::parse(path, &buffer).ok();
+    let field = |key| contents.as_ref().and_then(|contents| contents.field(key));
+    let age = match field("started") {
+        Some(started) => from_timestamp(started).ok(),
+        // The PID file may have been removed since it was checked.
+        None if state != LockState::Missing => std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+        None => None,
+    }
+    .map(|started| {
+        SystemTime::now()
+            .duration_since(started)
+            .unwrap_or(Duration::ZERO)
+    });
+
+    let status = JsonStatus {
+        state: name,
+        pid,
+        alive: state.is_locked(),
+        stale: matches!(state, LockState::Stale(_)),
+        hostname: field("host"),
+        age_secs: age.map(|age| age.as_secs()),
+    };
+    serde_json::to_string(&status).map_err(io::Error::other)
+}
+
+impl PidFile {
+    /// Describe the state of the PID file at this path, in a line ready to print.
+    ///
+    /// This is meant for the `status` command of command line tools, and produces e.g.
+    /// `running (pid 1234, host foo, up 3h)`, `stale lock from pid 1234`, or `not running`.
+    /// The host and uptime are included when they were recorded with
+    /// [`Builder::record_host`](crate::Builder::record_host) and
+    /// [`Builder::record_start_time`](crate::Builder::record_start_time). The format may
+    /// change between releases, so use [`PidFile::check_many`] or
+    /// [`PidFile::validate`] to inspect PID files programmatically.
+    pub fn describe(path: &Path) -> Result<String, io::Error> {
+        status(path).map(|status| status.to_string())
+    }
+
+    /// Get the status of the PID file at this path, for command line `status` output.
+    ///
+    /// The [`Status`] displays as the line produced by [`PidFile::describe`], and serializes
+    /// with `serde` for machine-readable output. For example, as JSON:
+    ///
+    /// ```json
+    /// {"state":"running","pid":1234,"host":"foo","uptime_secs":10800}
+    /// ```
+    #[cfg(feature = "status")]
+    pub fn status(path: &Path) -> Result<Status, io::Error> {
+        status(path)
+    }
+
+    /// Get the status of the PID file at this path as a compact JSON object, e.g. for a
+    /// `status` command whose output is piped to `jq`.
+    ///
+    /// Every key is always present, so the schema is the same whatever the state:
+    ///
+    /// ```json
+    /// {"state":"running","pid":1234,"alive":true,"stale":false,"hostname":"foo","age_secs":10800}
+    /// ```
+    ///
+    /// - `state` is one of `running`, `stale`, `free`, `empty` or `invalid`, as for
+    ///   [`Status`].
+    /// - `pid` is the PID in the PID file, or `null` if there isn't a valid one.
+    /// - `alive` is whether the PID file is held, as for [`PidFile::is_locked`].
+    /// - `stale` is whether the PID file was left behind by a process which is no longer
+    ///   running.
+    /// - `hostname` is the host of the owner, when recorded with
+    ///   [`Builder::record_host`](crate::Builder::record_host), and otherwise `null`.
+    /// - `age_secs` is how long ago the PID file was created, in whole seconds, as for
+    ///   [`PidFile::age`], or `null` if there is no PID file.
+    #[cfg(feature = "json")]
+    pub fn status_json(path: &Path) -> Result<String, io::Error> {
+        status_json(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn test_describe() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("describe.pid");
+        assert_eq!(PidFile::describe(&path).unwrap(), "not running");
+
+        let pid_file = Builder::new(&path)
+            .record_host(true)
+            .record_start_time(true)
+            .create()
+            .unwrap();
+        let description = PidFile::describe(&path).unwrap();
+        let expected = format!(
+            "running (pid {}, host {}, up ",
+            std::process::id(),
+            crate::hostname().unwrap()
+        );
+        assert!(description.starts_with(&expected), "{description}");
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, pid.to_string()).unwrap();
+        assert_eq!(
+            PidFile::describe(&path).unwrap(),
+            format!("stale lock from pid {pid}")
+        );
+    }
+
+    #[cfg(feature = "status")]
+    #[test]
+    fn test_status_json() {
+        let status = Status::Running {
+            pid: 1234,
+            host: Some("foo".to_owned()),
+            uptime: Some(Duration::from_secs(10800)),
+        };
+        assert_eq!(status.to_string(), "running (pid 1234, host foo, up 3h)");
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            r#"{"state":"running","pid":1234,"host":"foo","uptime_secs":10800}"#
+        );
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("status.pid");
+        let status = PidFile::status(&path).unwrap();
+        assert_eq!(status, Status::Free);
+        assert_eq!(
+            serde_json::to_string(&status).unwrap(),
+            r#"{"state":"free"}"#
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_status_json_schema() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("status-json.pid");
+        assert_eq!(
+            PidFile::status_json(&path).unwrap(),
+            r#"{"state":"free","pid":null,"alive":false,"stale":false,"hostname":null,"age_secs":null}"#
+        );
+
+        let pid_file = Builder::new(&path).record_host(true).create().unwrap();
+        let status: serde_json::Value =
+            serde_json::from_str(&PidFile::status_json(&path).unwrap()).unwrap();
+        assert_eq!(status["state"], "running");
+        assert_eq!(status["pid"], std::process::id());
+        assert_eq!(status["alive"], true);
+        assert_eq!(status["stale"], false);
+        assert_eq!(status["hostname"], crate::hostname().unwrap());
+        assert!(status["age_secs"].as_u64().unwrap() < 60);
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, pid.to_string()).unwrap();
+        let status: serde_json::Value =
+            serde_json::from_str(&PidFile::status_json(&path).unwrap()).unwrap();
+        assert_eq!(status["state"], "stale");
+        assert_eq!(status["pid"], pid);
+        assert_eq!(status["alive"], false);
+        assert_eq!(status["stale"], true);
+        assert_eq!(status["hostname"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(12)), "12s");
+        assert_eq!(format_duration(Duration::from_secs(300)), "5m");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 59)), "3h");
+        assert_eq!(format_duration(Duration::from_secs(2 * 86400)), "2d");
+    }
+}
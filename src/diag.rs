@@ -0,0 +1,74 @@
+//! Diagnostics, which are emitted with `tracing` when the `tracing` feature is enabled, and
+//! compiled out otherwise.
+//!
+//! Call sites use these macros rather than `tracing`'s directly, so that they compile either
+//! way. Without `tracing`, the macros accept the same syntax, and type check their fields and
+//! messages, but never evaluate them.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, debug_span, error, info, trace, trace_span, warn};
+
+#[cfg(not(feature = "tracing"))]
+mod noop {
+    /// A span which does nothing, in place of a `tracing` span.
+    pub(crate) struct Span;
+
+    impl Span {
+        /// Enter this span, which does nothing.
+        pub(crate) fn entered(self) -> Self {
+            self
+        }
+    }
+
+    /// Consume the fields and message of an event or span, without evaluating them.
+    macro_rules! fields {
+        () => {};
+        ($fmt:literal $($args:tt)*) => {
+            let _ = format_args!($fmt $($args)*);
+        };
+        ($name:ident = % $value:expr $(, $($rest:tt)*)?) => {
+            let _ = &$value;
+            $crate::diag::fields!($($($rest)*)?);
+        };
+        ($name:ident = ? $value:expr $(, $($rest:tt)*)?) => {
+            let _ = &$value;
+            $crate::diag::fields!($($($rest)*)?);
+        };
+        ($name:ident = $value:expr $(, $($rest:tt)*)?) => {
+            let _ = &$value;
+            $crate::diag::fields!($($($rest)*)?);
+        };
+        (% $name:ident $(, $($rest:tt)*)?) => {
+            let _ = &$name;
+            $crate::diag::fields!($($($rest)*)?);
+        };
+        (? $name:ident $(, $($rest:tt)*)?) => {
+            let _ = &$name;
+            $crate::diag::fields!($($($rest)*)?);
+        };
+    }
+
+    macro_rules! event {
+        ($($arg:tt)*) => {
+            if false {
+                $crate::diag::fields!($($arg)*);
+            }
+        };
+    }
+
+    macro_rules! span {
+        ($name:literal $(, $($arg:tt)*)?) => {{
+            if false {
+                $crate::diag::fields!($($($arg)*)?);
+            }
+            $crate::diag::Span
+        }};
+    }
+
+    pub(crate) use fields;
+    pub(crate) use {event as debug, event as error, event as info, event as trace, event as warn};
+    pub(crate) use {span as debug_span, span as trace_span};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use noop::*;
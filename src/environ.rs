@@ -0,0 +1,97 @@
+//! Reading the environment of the process which owns a PID file, for diagnostics (Linux).
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use crate::{pid_file_owner, CheckPolicy, PidFile};
+
+/// Parse the contents of `/proc/<pid>/environ`: `KEY=value` entries separated by NULs.
+///
+/// Entries without a `=` are skipped, and invalid UTF-8 is replaced.
+fn parse_environ(environ: &[u8]) -> BTreeMap<String, String> {
+    environ
+        .split(|&byte| byte == 0)
+        .filter_map(|entry| {
+            let split = entry.iter().position(|&byte| byte == b'=')?;
+            let (key, value) = (&entry[..split], &entry[split + 1..]);
+            Some((
+                String::from_utf8_lossy(key).into_owned(),
+                String::from_utf8_lossy(value).into_owned(),
+            ))
+        })
+        .collect()
+}
+
+impl PidFile {
+    /// Read the environment of the process which holds the PID file at this path, e.g. to
+    /// see which configuration a process which was thought to be dead has loaded.
+    ///
+    /// This reads `/proc/<pid>/environ`, which reflects the environment the owner was started
+    /// with, and not changes it has made since. Reading it requires the same permissions as
+    /// attaching a debugger to the owner: usually, running as the same user as the owner, or
+    /// as root. When reading it isn't permitted, this returns an error with
+    /// [`io::ErrorKind::PermissionDenied`].
+    ///
+    /// Returns `Ok(None)` when there is no PID file, or when its owner is no longer running.
+    /// The environment of a process can contain secrets, so take care when logging it.
+    pub fn owner_environ(path: &Path) -> Result<Option<BTreeMap<String, String>>, io::Error> {
+        let owner = pid_file_owner(path, &CheckPolicy::default())?;
+        let Some(pid) = owner.alive() else {
+            return Ok(None);
+        };
+
+        match std::fs::read(format!("/proc/{pid}/environ")) {
+            // Zombies, and processes which have exited since the check, have no environment.
+            Ok(environ) if environ.is_empty() => Ok(None),
+            Ok(environ) => Ok(Some(parse_environ(&environ))),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_parse_environ() {
+        let environ = parse_environ(b"HOME=/root\0EMPTY=\0NOEQUALS\0EQ=a=b\0");
+        assert_eq!(environ.len(), 3);
+        assert_eq!(environ["HOME"], "/root");
+        assert_eq!(environ["EMPTY"], "");
+        assert_eq!(environ["EQ"], "a=b");
+    }
+
+    #[test]
+    fn test_owner_environ() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-environ.pid");
+        assert_eq!(PidFile::owner_environ(&path).unwrap(), None);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .env("PIDFILE_ENVIRON_TEST", "loaded")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, child.id().to_string()).unwrap();
+
+        // The child briefly has our environment while it is still being exec'd.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let loaded = loop {
+            let environ = PidFile::owner_environ(&path).unwrap().unwrap_or_default();
+            match environ.get("PIDFILE_ENVIRON_TEST") {
+                Some(loaded) => break loaded.clone(),
+                None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                None => panic!("no environment for PID {}", child.id()),
+            }
+        };
+        assert_eq!(loaded, "loaded");
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(PidFile::owner_environ(&path).unwrap(), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! Error types which don't depend on `std`.
+//!
+//! These are available without the `std` feature, so that supervisors in `no_std`
+//! environments (with `alloc`) can refer to the same errors as the rest of the crate.
+//! With `std`, these errors are carried inside the [`std::io::Error`]s returned by
+//! the rest of the API, and can be recovered with [`std::io::Error::get_ref`].
+//!
+//! Underlying errors are reported by [`std::error::Error::source`] rather than in the
+//! [`Display`](fmt::Display) output, so error reporters (e.g. `anyhow` or `eyre`) print
+//! each cause in the chain once.
+
+use alloc::string::String;
+use core::fmt;
+use core::num::{IntErrorKind, ParseIntError};
+use core::time::Duration;
+
+/// An error specific to PID files.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum PidFileError {
+    /// The PID file is held by another running process.
+    InUse {
+        /// The path to the PID file.
+        path: String,
+
+        /// The PID of the process which holds the PID file.
+        pid: libc::pid_t,
+    },
+
+    /// The PID file was left behind by a process which is no longer running, but was
+    /// modified too recently to reclaim.
+    Settling {
+        /// The path to the PID file.
+        path: String,
+
+        /// The PID of the process which left the PID file behind.
+        pid: libc::pid_t,
+
+        /// How long until the PID file can be reclaimed.
+        remaining: Duration,
+    },
+
+    /// The PID file is already held by this process.
+    HeldByThisProcess {
+        /// The path to the PID file.
+        path: String,
+    },
+
+    /// The file at the path doesn't carry the magic line set with `Builder::magic`, so it
+    /// may not be a PID file at all, and was left alone.
+    Unrecognized {
+        /// The path to the file.
+        path: String,
+    },
+
+    /// The path of the PID file resolves outside the directory it must be created in, given
+    /// to `PidFile::new_checked`.
+    OutsideDirectory {
+        /// The path to the PID file, as given.
+        path: String,
+
+        /// The directory which the PID file must be in.
+        dir: String,
+    },
+
+    /// The directory which should contain the PID file doesn't exist.
+    MissingDirectory {
+        /// The path to the missing directory.
+        path: String,
+    },
+
+    /// The PID file doesn't contain a valid PID.
+    ///
+    /// The message distinguishes a number which is too large or too small for a PID, which
+    /// means the PID file is corrupt, from contents which aren't a number at all.
+    InvalidPid(ParseIntError),
+
+    /// The PID file contains a PID which is zero or negative, and so can't be checked.
+    NonPositivePid(libc::pid_t),
+
+    /// The process is not running as the user required to create the PID file.
+    #[cfg(unix)]
+    WrongUser {
+        /// The effective user ID required.
+        expected: libc::uid_t,
+
+        /// The effective user ID of this process.
+        actual: libc::uid_t,
+    },
+
+    /// An I/O error occurred while working with the PID file.
+    ///
+    /// The error is shared, so that [`PidFileError`] remains cheap to clone. Two `Io`
+    /// errors are only equal if they share the same underlying error.
+    #[cfg(feature = "std")]
+    Io(alloc::sync::Arc<std::io::Error>),
+}
+
+impl PartialEq for PidFileError {
+    fn eq(&self, other: &Self) -> bool {
+        use PidFileError::*;
+
+        match (self, other) {
+            (InUse { path, pid }, InUse { path: p, pid: q }) => path == p && pid == q,
+            (
+                Settling {
+                    path,
+                    pid,
+                    remaining,
+                },
+                Settling {
+                    path: p,
+                    pid: q,
+                    remaining: r,
+                },
+            ) => path == p && pid == q && remaining == r,
+            (HeldByThisProcess { path }, HeldByThisProcess { path: p }) => path == p,
+            (Unrecognized { path }, Unrecognized { path: p }) => path == p,
+            (OutsideDirectory { path, dir }, OutsideDirectory { path: p, dir: d }) => {
+                path == p && dir == d
+            }
+            (MissingDirectory { path }, MissingDirectory { path: p }) => path == p,
+            (InvalidPid(error), InvalidPid(e)) => error == e,
+            (NonPositivePid(pid), NonPositivePid(q)) => pid == q,
+            #[cfg(unix)]
+            (
+                WrongUser { expected, actual },
+                WrongUser {
+                    expected: e,
+                    actual: a,
+                },
+            ) => expected == e && actual == a,
+            #[cfg(feature = "std")]
+            (Io(error), Io(e)) => alloc::sync::Arc::ptr_eq(error, e),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for PidFileError {}
+
+impl fmt::Display for PidFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PidFileError::InUse { path, pid } => {
+                write!(f, "PID File {path} is held by PID {pid}")
+            }
+            PidFileError::Settling {
+                path,
+                pid,
+                remaining,
+            } => write!(
+                f,
+                "PID File {path} was left by PID {pid}, and can be reclaimed in {remaining:?}"
+            ),
+            PidFileError::HeldByThisProcess { path } => {
+                write!(f, "PID File {path} is already held by this process")
+            }
+            PidFileError::Unrecognized { path } => write!(
+                f,
+                "{path} is not a PID file written by this application, refusing to replace it"
+            ),
+            PidFileError::OutsideDirectory { path, dir } => {
+                write!(f, "PID File {path} is outside the allowed directory {dir}")
+            }
+            PidFileError::MissingDirectory { path } => write!(
+                f,
+                "directory {path} does not exist, create it or use Builder::create_dirs"
+            ),
+            PidFileError::InvalidPid(error) => match error.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    write!(f, "expected a PID, got a number out of range for a PID")
+                }
+                _ => write!(f, "expected a PID"),
+            },
+            PidFileError::NonPositivePid(pid) => write!(f, "expected a positive PID, got {pid}"),
+            #[cfg(unix)]
+            PidFileError::WrongUser { expected, actual } => {
+                write!(f, "must run as uid {expected}, but running as uid {actual}")
+            }
+            #[cfg(feature = "std")]
+            PidFileError::Io(_) => write!(f, "I/O error"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PidFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PidFileError::InvalidPid(error) => Some(error),
+            PidFileError::Io(error) => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PidFileError {
+    fn from(error: std::io::Error) -> Self {
+        PidFileError::Io(alloc::sync::Arc::new(error))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<PidFileError> for std::io::Error {
+    fn from(error: PidFileError) -> Self {
+        let kind = match &error {
+            PidFileError::InUse { .. }
+            | PidFileError::Settling { .. }
+            | PidFileError::HeldByThisProcess { .. } => std::io::ErrorKind::AddrInUse,
+            PidFileError::Unrecognized { .. } => std::io::ErrorKind::AlreadyExists,
+            PidFileError::OutsideDirectory { .. } => std::io::ErrorKind::PermissionDenied,
+            PidFileError::MissingDirectory { .. } => std::io::ErrorKind::NotFound,
+            PidFileError::InvalidPid(_) | PidFileError::NonPositivePid(_) => {
+                std::io::ErrorKind::InvalidData
+            }
+            #[cfg(unix)]
+            PidFileError::WrongUser { .. } => std::io::ErrorKind::PermissionDenied,
+            PidFileError::Io(error) => error.kind(),
+        };
+        std::io::Error::new(kind, error)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_source() {
+        let error = PidFileError::InvalidPid("x".parse::<i32>().unwrap_err());
+        assert_eq!(error.to_string(), "expected a PID");
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "invalid digit found in string"
+        );
+
+        let error = PidFileError::InvalidPid("99999999999".parse::<i32>().unwrap_err());
+        assert_eq!(
+            error.to_string(),
+            "expected a PID, got a number out of range for a PID"
+        );
+
+        let error = PidFileError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(
+            std::io::Error::from(error.clone()).kind(),
+            std::io::ErrorKind::NotFound
+        );
+        assert_eq!(error, error.clone());
+        let source = error.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::NotFound);
+    }
+}
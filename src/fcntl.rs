@@ -0,0 +1,206 @@
+//! `fcntl` record locks on PID files, for interoperating with daemons which use them.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+use crate::mode::set_mode;
+use crate::{contents_owner, reclaim, CheckPolicy, PidFileError};
+
+/// A `flock` structure describing a write lock on the whole file.
+fn whole_file(lock_type: libc::c_int) -> libc::flock {
+    // SAFETY: `flock` is plain old data, for which all zeroes is a valid value.
+    #[allow(unsafe_code)]
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = 0;
+    lock.l_len = 0;
+    lock
+}
+
+/// Take a write lock on the whole of `file` with `fcntl(F_SETLK)`, without blocking.
+///
+/// If another process holds a conflicting lock, this returns [`PidFileError::InUse`] with
+/// the PID of that process.
+fn lock(file: &File, path: &Path) -> Result<(), io::Error> {
+    let mut lock = whole_file(libc::F_WRLCK);
+
+    // SAFETY: `file` is a valid file descriptor, and `lock` is valid for the duration of
+    // this call.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) };
+    if result == 0 {
+        return Ok(());
+    }
+
+    let error = io::Error::last_os_error();
+    if !matches!(error.raw_os_error(), Some(libc::EACCES | libc::EAGAIN)) {
+        return Err(error);
+    }
+
+    // SAFETY: As above. `F_GETLK` fills in `lock` with the conflicting lock.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) };
+    if result != 0 || lock.l_type == libc::F_UNLCK as libc::c_short {
+        // The conflicting lock was released in the meantime, but we don't retry, so that
+        // only one contender ever observes the lock as free.
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!(
+                "PID File {path} is locked by another process",
+                path = path.display()
+            ),
+        ));
+    }
+
+    crate::quiet::in_use!(error, path=%path.display(), pid=%lock.l_pid, "PID File {path} is locked by PID {pid}", path = path.display(), pid = lock.l_pid);
+    Err(PidFileError::InUse {
+        path: path.display().to_string(),
+        pid: lock.l_pid,
+    }
+    .into())
+}
+
+/// Open the PID file at `path`, lock it, and write `contents` to it.
+///
+/// The existing contents are checked with `policy` before they are replaced, in case the
+/// PID file is held by a process which doesn't use `fcntl` locks. Returns the locked file,
+/// along with the PID of the process which left a stale PID file behind, if there was one.
+pub(crate) fn create_locked(
+    path: &Path,
+    policy: &CheckPolicy,
+    contents: &str,
+    mode: u32,
+) -> Result<(File, Option<libc::pid_t>), io::Error> {
+    let mut file = open(path)?;
+    lock(&file, path)?;
+
+    let mut existing = String::new();
+    file.read_to_string(&mut existing)?;
+    let mut reclaimed = None;
+    if !existing.is_empty() {
+        // The file is rewritten in place below, so there is nothing to remove.
+        reclaimed = reclaim(path, contents_owner(path, Ok(existing), policy))?;
+    }
+
+    set_mode(&file, mode)?;
+    rewrite(&file, contents)?;
+    Ok((file, reclaimed))
+}
+
+/// Open the file at `path`, lock it, and leave it empty, for a marker which doesn't record
+/// a PID.
+///
+/// The existing contents aren't checked, since the lock alone decides who holds the file.
+pub(crate) fn create_marker(path: &Path, mode: u32) -> Result<File, io::Error> {
+    let file = open(path)?;
+    lock(&file, path)?;
+    set_mode(&file, mode)?;
+    file.set_len(0)?;
+    Ok(file)
+}
+
+/// Open the file at `path` for locking, creating it if it doesn't exist, without truncating
+/// it before it is locked.
+fn open(path: &Path) -> Result<File, io::Error> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Read the contents of a locked PID file through its file descriptor.
+///
+/// Opening and closing another file descriptor for the PID file would release the lock.
+pub(crate) fn read(mut file: &File) -> Result<String, io::Error> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Replace the contents of a locked PID file in place.
+pub(crate) fn rewrite(mut file: &File, contents: &str) -> Result<(), io::Error> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(contents.as_bytes())?;
+    file.set_len(contents.len() as u64)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Builder, PidFile};
+
+    /// Whether another process sees a write lock on the file at `path`.
+    ///
+    /// The file is only opened in the child, since closing any file descriptor for the file
+    /// in this process would release our lock.
+    fn locked_elsewhere(path: &Path) -> bool {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap();
+
+        // SAFETY: The child only calls async-signal-safe functions before exiting.
+        #[allow(unsafe_code)]
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0, "fork failed");
+            if pid == 0 {
+                let fd = libc::open(name.as_ptr(), libc::O_RDONLY);
+                let mut lock = whole_file(libc::F_WRLCK);
+                let result = libc::fcntl(fd, libc::F_GETLK, &mut lock);
+                libc::_exit(
+                    if result == 0 && lock.l_type != libc::F_UNLCK as libc::c_short {
+                        0
+                    } else {
+                        1
+                    },
+                );
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+        }
+    }
+
+    #[test]
+    fn test_fcntl_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fcntl.pid");
+
+        let pid_file = Builder::new(&path).fcntl_lock(true).create().unwrap();
+        assert!(locked_elsewhere(&path));
+
+        pid_file.heartbeat().unwrap();
+        assert!(locked_elsewhere(&path));
+
+        // Reading the file from this process releases the lock, so this must come last.
+        assert!(PidFile::last_heartbeat(&path).unwrap().is_some());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_fcntl_marker() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fcntl.lock");
+
+        // A marker left behind is locked again, whatever it contains.
+        std::fs::write(&path, "1").unwrap();
+        let marker = Builder::new(&path)
+            .fcntl_lock(true)
+            .write_pid(false)
+            .create()
+            .unwrap();
+        assert!(locked_elsewhere(&path));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        drop(marker);
+        assert!(!path.exists());
+    }
+}
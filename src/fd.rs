@@ -0,0 +1,266 @@
+//! Writing the PID to a file which is already open, such as a file descriptor passed in by a
+//! service manager like systemd.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+use crate::registry::Registration;
+use crate::{contents_owner, current_pid, fcntl, reclaim, CheckPolicy, PidFile};
+
+impl PidFile {
+    /// Write the PID of this process to an already open file descriptor.
+    ///
+    /// Some service managers, such as systemd, hand the daemon an open file to write its PID
+    /// to, rather than a path to create. The file is truncated and the PID written to it
+    /// through `fd`, which must be open for writing (and for reading, to use methods such as
+    /// [`PidFile::heartbeat`]).
+    ///
+    /// The lifecycle differs from PID files created at a path:
+    ///
+    /// - The file is never opened by path, so the existing contents are not checked, and no
+    ///   other process is prevented from writing to it. The service manager is responsible
+    ///   for running a single instance.
+    /// - When the [`PidFile`] is dropped, the file is truncated rather than removed, since
+    ///   it belongs to the service manager. `fd` is then closed.
+    /// - [`PidFile::path`] is the path which `fd` refers to, where it can be found, and
+    ///   otherwise its entry in `/proc/self/fd`. The PID file is not tracked by the
+    ///   in-process registry used by [`PidFile::new`], and can't be moved with
+    ///   [`PidFile::rename`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::os::fd::{FromRawFd, OwnedFd};
+    /// use pidfile::PidFile;
+    ///
+    /// # fn main() -> Result<(), std::io::Error> {
+    /// // The first file descriptor passed with `LISTEN_FDS`.
+    /// // SAFETY: The service manager passes this file descriptor to us, and nothing else owns it.
+    /// let fd = unsafe { OwnedFd::from_raw_fd(3) };
+    /// let pidfile = PidFile::from_fd(fd)?;
+    /// # drop(pidfile);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_fd(fd: OwnedFd) -> Result<Self, io::Error> {
+        let proc_path = PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()));
+        let path = std::fs::read_link(&proc_path).unwrap_or(proc_path);
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+
+        let file = File::from(fd);
+        let pid = current_pid()?;
+        fcntl::rewrite(&file, &pid.to_string())?;
+        crate::diag::trace!(%pid, path=%path.display(), "Wrote PID to passed file descriptor for {path}", path = path.display());
+
+        let mut pidfile = PidFile::locked(path, pid, None);
+        pidfile.lock_file = Some(file);
+        pidfile.passed_fd = true;
+        pidfile.written = pid.to_string();
+        Ok(pidfile)
+    }
+
+    /// Create a PID file at `path` using a handle to it which the caller has already opened.
+    ///
+    /// This is for callers which open the PID file themselves, e.g. to take their own lock
+    /// on it, and want to avoid opening it a second time. Unlike [`PidFile::from_fd`], the
+    /// PID file is managed just like one created with [`PidFile::new`]:
+    ///
+    /// - The existing contents are read through `file`, and this fails with
+    ///   [`io::ErrorKind::AddrInUse`] if they belong to a running process. Stale PID files
+    ///   are reclaimed.
+    /// - The PID is written through `file`, which must be open for reading and writing.
+    /// - When the [`PidFile`] is dropped, the file at `path` is removed, and then `file` is
+    ///   closed.
+    ///
+    /// The [`PidFile`] takes ownership of `file`, and keeps it open until it is dropped. If
+    /// `path` isn't the file which `file` refers to, this fails with
+    /// [`io::ErrorKind::InvalidInput`].
+    pub fn from_file(file: File, path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let path = path.into();
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+
+        let opened = file.metadata()?;
+        let found = std::fs::metadata(&path)?;
+        if (opened.dev(), opened.ino()) != (found.dev(), found.ino()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the open file is not the PID file at {path}",
+                    path = path.display()
+                ),
+            ));
+        }
+
+        let registration = Registration::new(&path)?;
+        let existing = fcntl::read(&file)?;
+        let mut reclaimed = None;
+        if !existing.trim().is_empty() {
+            // The file is rewritten in place below, so there is nothing to remove.
+            let owner = contents_owner(&path, Ok(existing), &CheckPolicy::default());
+            reclaimed = reclaim(&path, owner)?;
+        }
+
+        let pid = current_pid()?;
+        fcntl::rewrite(&file, &pid.to_string())?;
+        crate::diag::trace!(%pid, path=%path.display(), "Wrote PID to open file for {path}", path = path.display());
+
+        let mut pidfile = PidFile::locked(path, pid, Some(registration));
+        pidfile.lock_file = Some(file);
+        pidfile.reclaimed = reclaimed;
+        pidfile.written = pid.to_string();
+        Ok(pidfile)
+    }
+}
+
+/// Appends status lines to PID files which keep their file open.
+///
+/// This works for PID files which hold an open handle on the file: those created with
+/// [`Builder::fcntl_lock`](crate::Builder::fcntl_lock), [`PidFile::from_fd`] or
+/// [`PidFile::from_file`]. Writes to other PID files fail with
+/// [`io::ErrorKind::Unsupported`]; use [`Builder::create_with_file`](crate::Builder::create_with_file)
+/// to get a handle to write to instead.
+///
+/// Writes always go at the end of the file, after the PID line, never over it, so readers
+/// can still parse the PID. If the file doesn't end with a newline, one is added before the
+/// first write, so the status starts on a line of its own.
+///
+/// ```rust,no_run
+/// use std::io::Write;
+/// use pidfile::PidFile;
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// let mut pidfile = PidFile::builder("/tmp/myapp.pid").fcntl_lock(true).create()?;
+/// writeln!(pidfile, "socket=/run/myapp.sock")?;
+/// # Ok(())
+/// # }
+/// ```
+impl Write for PidFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(mut file) = self.lock_file.as_ref() else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only PID files which keep their file open can be written to",
+            ));
+        };
+
+        if file.seek(SeekFrom::End(0))? > 0 {
+            let mut last = [0; 1];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last != *b"\n" {
+                file.write_all(b"\n")?;
+            }
+        }
+        file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.lock_file.as_ref() {
+            Some(mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_from_fd() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("fd.pid");
+        std::fs::write(&path, "left over from a previous run").unwrap();
+
+        let file = File::options().read(true).write(true).open(&path).unwrap();
+        let pid_file = PidFile::from_fd(file.into()).unwrap();
+        assert_eq!(pid_file.path(), path.canonicalize().unwrap());
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        pid_file.heartbeat().unwrap();
+        assert!(PidFile::last_heartbeat(&path).unwrap().is_some());
+
+        drop(pid_file);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+
+    #[test]
+    fn test_write_status() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("status.pid");
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .unwrap();
+
+        let mut pid_file = PidFile::from_file(file, &path).unwrap();
+        writeln!(pid_file, "status=starting").unwrap();
+        writeln!(pid_file, "socket=/run/test.sock").unwrap();
+        pid_file.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!(
+                "{}\nstatus=starting\nsocket=/run/test.sock\n",
+                std::process::id()
+            )
+        );
+        assert_eq!(
+            PidFile::read_field(&path, "status").unwrap().as_deref(),
+            Some("starting")
+        );
+        drop(pid_file);
+
+        let mut pid_file = PidFile::new(&path).unwrap();
+        let error = writeln!(pid_file, "status=ok").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_from_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("file.pid");
+        let open = || {
+            File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .unwrap()
+        };
+
+        let pid_file = PidFile::from_file(open(), &path).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        assert_eq!(
+            PidFile::from_file(open(), &path).unwrap_err().kind(),
+            io::ErrorKind::AddrInUse
+        );
+        pid_file.heartbeat().unwrap();
+        drop(pid_file);
+        assert!(!path.exists());
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, child.id().to_string()).unwrap();
+        let error = silenced(|| PidFile::from_file(open(), &path).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let other = tmp.path().join("other.pid");
+        std::fs::write(&other, "").unwrap();
+        assert_eq!(
+            PidFile::from_file(open(), &other).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! A minimal C interface, so that C components can share the same PID files.
+//!
+//! The functions report errors as positive `errno` values. A handle created by
+//! [`pidfile_create`] is owned by the caller until it is passed to [`pidfile_remove`],
+//! which removes the PID file and frees the handle.
+
+use std::ffi::{c_char, c_int, CStr, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::PidFile;
+
+/// The `errno` value which best describes an error.
+fn errno(error: &io::Error) -> c_int {
+    if let Some(code) = error.raw_os_error() {
+        return code;
+    }
+
+    match error.kind() {
+        io::ErrorKind::AddrInUse => libc::EADDRINUSE,
+        io::ErrorKind::PermissionDenied => libc::EACCES,
+        io::ErrorKind::NotFound => libc::ENOENT,
+        io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData => libc::EINVAL,
+        io::ErrorKind::TimedOut => libc::ETIMEDOUT,
+        io::ErrorKind::Unsupported => libc::ENOTSUP,
+        _ => libc::EIO,
+    }
+}
+
+/// Borrow a path from a C string.
+///
+/// # Safety
+///
+/// `path` must be null, or a valid, NUL-terminated C string which outlives the result.
+#[allow(unsafe_code)]
+unsafe fn c_path<'a>(path: *const c_char) -> Result<&'a Path, c_int> {
+    if path.is_null() {
+        return Err(libc::EINVAL);
+    }
+
+    let path = CStr::from_ptr(path);
+    Ok(Path::new(OsStr::from_bytes(path.to_bytes())))
+}
+
+/// Create a PID file for this process at `path`, like [`PidFile::new`].
+///
+/// On success, this returns `0` and stores a handle in `*out`. The caller owns the handle,
+/// and must eventually pass it to [`pidfile_remove`] exactly once. On failure, this returns
+/// an `errno` value (`EADDRINUSE` when the PID file is held by another process), and leaves
+/// `*out` unchanged.
+///
+/// # Safety
+///
+/// `path` must be a valid, NUL-terminated C string, and `out` must be valid for writes.
+#[no_mangle]
+#[allow(unsafe_code)]
+pub unsafe extern "C" fn pidfile_create(path: *const c_char, out: *mut *mut PidFile) -> c_int {
+    if out.is_null() {
+        return libc::EINVAL;
+    }
+
+    let path = match c_path(path) {
+        Ok(path) => path,
+        Err(code) => return code,
+    };
+
+    match PidFile::new(path) {
+        Ok(pidfile) => {
+            *out = Box::into_raw(Box::new(pidfile));
+            0
+        }
+        Err(error) => errno(&error),
+    }
+}
+
+/// Check whether the PID file at `path` is held, like [`PidFile::is_locked`].
+///
+/// Returns `1` if the PID file is held, `0` if it is not, or a negative `errno` value if
+/// the PID file could not be checked.
+///
+/// # Safety
+///
+/// `path` must be a valid, NUL-terminated C string.
+#[no_mangle]
+#[allow(unsafe_code)]
+pub unsafe extern "C" fn pidfile_is_locked(path: *const c_char) -> c_int {
+    let path = match c_path(path) {
+        Ok(path) => path,
+        Err(code) => return -code,
+    };
+
+    match PidFile::is_locked(path) {
+        Ok(locked) => c_int::from(locked),
+        Err(error) => -errno(&error),
+    }
+}
+
+/// Remove the PID file for a handle created by [`pidfile_create`], and free the handle.
+///
+/// The handle must not be used after this call. Passing a null handle does nothing.
+///
+/// # Safety
+///
+/// `pidfile` must be null, or a handle returned by [`pidfile_create`] which has not
+/// already been removed.
+#[no_mangle]
+#[allow(unsafe_code)]
+pub unsafe extern "C" fn pidfile_remove(pidfile: *mut PidFile) {
+    if !pidfile.is_null() {
+        drop(Box::from_raw(pidfile));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CString;
+    use std::ptr;
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_ffi() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-ffi.pid");
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+
+        // SAFETY: `c_path` is a valid C string, and `pidfile` is valid for writes.
+        unsafe {
+            assert_eq!(pidfile_is_locked(c_path.as_ptr()), 0);
+
+            let mut pidfile = ptr::null_mut();
+            assert_eq!(pidfile_create(c_path.as_ptr(), &mut pidfile), 0);
+            assert!(!pidfile.is_null());
+            assert_eq!(pidfile_is_locked(c_path.as_ptr()), 1);
+
+            let mut other = ptr::null_mut();
+            assert_eq!(
+                pidfile_create(c_path.as_ptr(), &mut other),
+                libc::EADDRINUSE
+            );
+            assert!(other.is_null());
+
+            pidfile_remove(pidfile);
+            assert!(!path.exists());
+            assert_eq!(pidfile_is_locked(ptr::null()), -libc::EINVAL);
+        }
+    }
+}
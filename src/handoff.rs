@@ -0,0 +1,113 @@
+//! Taking over a PID file from an outgoing process, for seamless restarts.
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::contents::Contents;
+use crate::registry::Registration;
+use crate::{current_pid, hostname, PidFile};
+
+impl PidFile {
+    /// Take over the PID file at this path, carrying over its metadata.
+    ///
+    /// This is for seamless restarts, where a new process takes over from an outgoing one
+    /// and should present continuity, e.g. keep the start time of the original service.
+    /// The existing PID file is read, and atomically replaced with one containing the PID
+    /// of this process and the existing metadata.
+    ///
+    /// Metadata describing the service is carried over as it is, such as the start time
+    /// recorded with [`Builder::record_start_time`](crate::Builder::record_start_time), the
+    /// lease from [`Builder::lease`](crate::Builder::lease), and any fields written with
+    /// [`PidFile::renew_contents`]. Metadata describing the process is reset: the host,
+    /// parent PID, and boot time are re-recorded for this process (but only if they were
+    /// recorded before), and the heartbeat is removed.
+    ///
+    /// The existing PID file must contain a valid PID, otherwise this returns an error with
+    /// [`io::ErrorKind::NotFound`] or [`io::ErrorKind::InvalidData`]. Whether its owner is
+    /// still running is not checked, since the outgoing process may still be shutting
+    /// down. It must release the PID file with [`PidFile::into_path`] (or exit without
+    /// dropping it), since dropping it would remove the new PID file.
+    pub fn new_cow(path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let path = path.into();
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+        let registration = Registration::new(&path)?;
+
+        let info = std::fs::read_to_string(&path)?;
+        let mut contents = Contents::parse(&path, &info)?;
+        let previous = contents.pid();
+
+        let pid = current_pid()?;
+        contents.set_pid(pid);
+        contents.remove_field("heartbeat");
+        if contents.field("host").is_some() {
+            contents.set_field("host", hostname()?);
+        }
+        if contents.field("boot").is_some() {
+            contents.set_field("boot", crate::boot::boot_time()?);
+        }
+        #[cfg(unix)]
+        if contents.field("ppid").is_some() {
+            contents.set_field("ppid", crate::parent_pid());
+        }
+
+        // Until the new contents are in place, the PID file still belongs to the outgoing
+        // process, and must not be removed if writing them fails.
+        let mut pidfile = PidFile::locked(path, pid, None);
+        pidfile.keep = true;
+        pidfile.written = contents.render();
+        pidfile.replace_contents(&pidfile.written)?;
+        registration.record_file_id();
+        pidfile.keep = false;
+        #[cfg(all(unix, feature = "signal-cleanup"))]
+        {
+            pidfile.published = crate::cleanup::publish(registration.key());
+        }
+        pidfile._registration = Some(registration);
+
+        crate::diag::debug!(%pid, %previous, path=%pidfile.path.display(), "Took over PID file {path} from PID {previous}", path = pidfile.path.display());
+        Ok(pidfile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn test_new_cow() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("handoff.pid");
+
+        let outgoing = Builder::new(&path)
+            .record_start_time(true)
+            .record_host(true)
+            .create()
+            .unwrap();
+        outgoing.heartbeat().unwrap();
+        outgoing.renew_contents([("version", "1")]).unwrap();
+        let started = PidFile::read_field(&path, "started").unwrap();
+        let path = outgoing.into_path();
+
+        // Pretend that the outgoing process is another one.
+        let mut contents =
+            Contents::parse(&path, &std::fs::read_to_string(&path).unwrap()).unwrap();
+        contents.set_pid(1);
+        std::fs::write(&path, contents.render()).unwrap();
+
+        let pid_file = PidFile::new_cow(&path).unwrap();
+        let contents = Contents::parse(&path, &pid_file.contents().unwrap()).unwrap();
+        assert_eq!(contents.pid(), std::process::id() as libc::pid_t);
+        assert_eq!(contents.field("started"), started.as_deref());
+        assert_eq!(contents.field("version"), Some("1"));
+        assert_eq!(contents.field("host"), Some(hostname().unwrap().as_str()));
+        assert_eq!(contents.field("heartbeat"), None);
+        drop(pid_file);
+        assert!(!path.exists());
+
+        assert_eq!(
+            PidFile::new_cow(&path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! Randomized delays between retries, to desynchronize processes which start together.
+
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// A source of random delays, uniformly distributed between zero and a maximum.
+///
+/// This uses SplitMix64, which is fast and statistically sound, though not cryptographically
+/// secure, which doesn't matter for spreading out retries.
+#[derive(Debug, Clone)]
+pub(crate) struct Jitter {
+    max: Duration,
+    state: u64,
+}
+
+impl Jitter {
+    /// Random delays of up to `max`, from `seed`, or from a fresh seed if there is none.
+    pub(crate) fn new(max: Duration, seed: Option<u64>) -> Self {
+        Self {
+            max,
+            state: seed.unwrap_or_else(fresh_seed),
+        }
+    }
+
+    /// The next random delay.
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let max = self.max.as_nanos().min(u64::MAX as u128) as u64;
+        if max == 0 {
+            return Duration::ZERO;
+        }
+
+        // Modulo bias is at most `max / 2^64`, which is negligible for any sensible delay.
+        Duration::from_nanos(self.next_u64() % max.saturating_add(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// A seed which differs between processes, and between calls in one process.
+fn fresh_seed() -> u64 {
+    // `RandomState` is randomly keyed, and mixing in the PID and the time keeps seeds apart
+    // even where its keys are only randomized once per process.
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    Instant::now().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_jitter() {
+        let max = Duration::from_millis(10);
+        let delays = |seed| {
+            let mut jitter = Jitter::new(max, Some(seed));
+            (0..100).map(|_| jitter.next_delay()).collect::<Vec<_>>()
+        };
+
+        let seeded = delays(42);
+        assert_eq!(seeded, delays(42));
+        assert_ne!(seeded, delays(43));
+        assert!(seeded.iter().all(|delay| *delay <= max));
+        assert!(seeded.iter().any(|delay| *delay > max / 2));
+        assert!(seeded.iter().any(|delay| *delay < max / 2));
+
+        let mut none = Jitter::new(Duration::ZERO, None);
+        assert_eq!(none.next_delay(), Duration::ZERO);
+    }
+}
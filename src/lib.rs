@@ -12,9 +12,39 @@
 //! files are created at a given path, and are automatically removed when the
 //! PID file object is dropped.
 //!
+//! When debugging, set the `PIDFILE_KEEP` environment variable to any value other
+//! than `0` to leave PID files in place when they are dropped, so they can be
+//! inspected after the process exits. When it is unset, PID files are always removed.
+//!
+//! # Features
+//!
+//! - `std` (enabled by default): the filesystem API. Without it, the crate is `no_std`
+//!   (with `alloc`), and only provides the pure types such as [`PidFileError`].
+//! - `cap-std`: `PidFile::new_in_dir`, which creates a PID file in a
+//!   [`cap_std::fs::Dir`](https://docs.rs/cap-std), for sandboxed applications without
+//!   ambient authority (Unix only).
+//! - `ffi`: the `ffi` module, a minimal C interface for sharing PID files with C code
+//!   (Unix only). Build the crate as a `cdylib` or `staticlib` to link it from C.
+//! - `json`: `PidFile::status_json`, which returns the status of a PID file as a JSON
+//!   string, for shell scripts and monitoring agents. Implies `status`.
+//! - `log`: also emit the crate's diagnostics as [`log`](https://docs.rs/log) records, for
+//!   applications which use the `log` facade rather than `tracing`. Records are only emitted
+//!   when no `tracing` subscriber is installed. Implies `tracing`.
+//! - `signal-cleanup`: `SignalCleanup`, which removes PID files when the process is
+//!   terminated by `SIGINT` or `SIGTERM` (Unix only).
+//! - `status`: `PidFile::status`, which returns a `Status` for command line `status`
+//!   output, which can be serialized with `serde` (e.g. for a `--json` flag).
+//! - `tempfile`: `PidFile::new_unique`, for PID files at unique paths (e.g. in tests).
+//! - `test-util`: `clear_registry`, to reset the PID files held by this process between
+//!   test cases. Never enable this in production.
+//! - `tracing` (enabled by default): emit the crate's diagnostics with
+//!   [`tracing`](https://docs.rs/tracing). Without it, the crate doesn't depend on
+//!   `tracing`, and its diagnostics are compiled out.
+//!
 //! # Example
 //!
 //! ```rust
+//! # #[cfg(feature = "std")] {
 //! use pidfile::PidFile;
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -23,182 +53,124 @@
 //!
 //!   Ok(())
 //! }
+//! # main().unwrap();
+//! # }
 //! ```
 
-use std::io;
-use std::path::{Path, PathBuf};
-
-/// A PID file is a file that contains the PID of a process. It is used to
-/// prevent multiple instances of a process from running at the same time,
-/// or to provide a lock for a resource which should only be accessed by one
-/// process at a time.
-#[derive(Debug)]
-pub struct PidFile {
-    path: PathBuf,
-}
-
-/// Check if a PID file is in use.
-///
-/// If the PID file corresponds to a currently unused PID, the file
-/// will be removed by this function.
-fn pid_file_in_use(path: &Path) -> Result<bool, io::Error> {
-    match std::fs::read_to_string(path) {
-        Ok(info) => {
-            let pid: libc::pid_t = info.trim().parse().map_err(|error| {
-                tracing::debug!(path=%path.display(), "Unable to parse PID file {path}: {error}", path = path.display());
-                io::Error::new(io::ErrorKind::InvalidData, "expected a PID")
-            })?;
-
-            // SAFETY: I dunno? Libc is probably fine.
-            #[allow(unsafe_code)]
-            let errno = unsafe { libc::kill(pid, 0) };
-
-            if errno == 0 {
-                tracing::debug!(%pid, "PID {pid} is still running", pid = pid);
-                // This PID still exists, so the pid file is valid.
-                return Ok(true);
-            }
-
-            if errno == -1 {
-                tracing::debug!(%pid, "Unkonwn error checking PID file: {errno}");
-                return Ok(false);
-            };
-
-            let error = io::Error::from_raw_os_error(errno);
-            match error.kind() {
-                io::ErrorKind::NotFound => Ok(false),
-                _ => Err(error),
-            }
-        }
-        Err(error) => match error.kind() {
-            io::ErrorKind::NotFound => Ok(false),
-            _ => Err(error),
-        },
-    }
-}
-
-impl PidFile {
-    /// Create a new PID file at the given path for this process.
-    ///
-    /// If the PID file already exists, this function will check if the
-    /// PID file is still in use. If the PID file is in use, this function
-    /// will return Err(io::ErrorKind::AddrInUse). If the PID file is not
-    /// in use, it will be removed and a new PID file will be created.
-    pub fn new(path: impl Into<PathBuf>) -> Result<Self, io::Error> {
-        let path = path.into();
-        if path.exists() {
-            match pid_file_in_use(&path) {
-                Ok(true) => {
-                    tracing::error!(path=%path.display(), "PID File {path} is already in use", path = path.display());
-                    return Err(io::Error::new(
-                        io::ErrorKind::AddrInUse,
-                        format!("PID File {path} is already in use", path = path.display()),
-                    ));
-                }
-                Ok(false) => {
-                    tracing::debug!(path=%path.display(), "Removing stale PID file at {path}", path = path.display());
-                    let _ = std::fs::remove_file(&path);
-                }
-                Err(error) if error.kind() == io::ErrorKind::InvalidData => {
-                    tracing::warn!(path=%path.display(), "Removing invalid PID file at {path}", path = path.display());
-                    let _ = std::fs::remove_file(&path);
-                }
-                Err(error) => {
-                    tracing::error!(path=%path.display(), "Unable to check PID file {path}: {error}", path = path.display());
-                    return Err(error);
-                }
-            }
-        }
-
-        // SAFETY: What could go wrong?
-        #[allow(unsafe_code)]
-        let pid = unsafe { libc::getpid() };
-
-        if pid <= 0 {
-            tracing::error!("libc::getpid() returned a negative PID: {pid}");
-            return Err(io::Error::new(io::ErrorKind::Other, "negative PID"));
-        }
-
-        std::fs::write(&path, format!("{}", pid))?;
-        tracing::trace!(%pid, path=%path.display(), "Locked PID file at {path}", path = path.display());
-
-        Ok(Self { path })
-    }
-
-    /// Check if a PID file is in use at this path.
-    ///
-    /// If this function returns an error, it indicates that either the PID file
-    /// could not be accessed, or when accessed, it contained data which did not look like a PID.
-    pub fn is_locked(path: &Path) -> Result<bool, io::Error> {
-        match pid_file_in_use(path) {
-            Ok(true) => Ok(true),
-            Ok(false) => Ok(false),
-            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
-                tracing::warn!(path=%path.display(), "Invalid PID file at {path}", path = path.display());
-                Ok(false)
-            }
-            Err(error) => {
-                tracing::error!(path=%path.display(), "Unable to check PID file {path}: {error}", path=path.display());
-                Err(error)
-            }
-        }
-    }
-}
-
-impl Drop for PidFile {
-    fn drop(&mut self) {
-        match std::fs::remove_file(&self.path) {
-            Ok(_) => {}
-            Err(error) => eprintln!(
-                "Encountered an error removing the PID file at {}: {}",
-                self.path.display(),
-                error
-            ),
-        }
-    }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    fn test_pid_file() {
-        let tmp = tempfile::tempdir().unwrap();
-        let path = tmp.path().join("pidfile-test.pid");
-        let pid_file = PidFile::new(path.clone()).unwrap();
-        assert!(PidFile::is_locked(&path).unwrap());
-        drop(pid_file);
-        assert!(!PidFile::is_locked(&path).unwrap());
-    }
-
-    #[test]
-    fn test_invalid_file() {
-        let path = Path::new("/tmp/pidfile-test.pid");
-        std::fs::write(path, "not a pid").unwrap();
-        tracing::subscriber::with_default(tracing::subscriber::NoSubscriber::new(), || {
-            assert!(
-                !PidFile::is_locked(path).unwrap(),
-                "Invalid file should not be locked."
-            )
-        });
-        assert!(
-            path.exists(),
-            "Invalid file should exist after checking for locks."
-        );
-
-        let pid_file =
-            tracing::subscriber::with_default(tracing::subscriber::NoSubscriber::new(), || {
-                PidFile::new(path).unwrap()
-            });
-        assert!(
-            PidFile::is_locked(path).unwrap(),
-            "PID file should be locked after creation."
-        );
-        drop(pid_file);
-        assert!(
-            !PidFile::is_locked(path).unwrap(),
-            "PID file should not be locked after drop."
-        );
-    }
-}
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod acquired;
+#[cfg(all(unix, feature = "std"))]
+mod at;
+#[cfg(feature = "std")]
+mod batch;
+#[cfg(feature = "std")]
+mod boot;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(all(unix, feature = "signal-cleanup"))]
+mod cleanup;
+#[cfg(feature = "std")]
+mod cmdline;
+#[cfg(feature = "std")]
+mod contents;
+#[cfg(feature = "std")]
+mod describe;
+#[cfg(feature = "std")]
+mod diag;
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod environ;
+mod error;
+#[cfg(all(unix, feature = "std"))]
+mod fcntl;
+#[cfg(all(unix, feature = "std"))]
+mod fd;
+#[cfg(all(unix, feature = "ffi"))]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod handoff;
+#[cfg(feature = "std")]
+mod jitter;
+#[cfg(feature = "std")]
+mod liveness;
+#[cfg(feature = "std")]
+mod lost;
+#[cfg(feature = "std")]
+mod mode;
+#[cfg(feature = "std")]
+mod multi;
+#[cfg(feature = "std")]
+mod observer;
+#[cfg(all(unix, feature = "std"))]
+mod paths;
+#[cfg(feature = "std")]
+mod pidfile;
+#[cfg(feature = "std")]
+mod quiet;
+#[cfg(feature = "std")]
+mod registry;
+#[cfg(feature = "std")]
+mod renewal;
+#[cfg(feature = "std")]
+mod repair;
+#[cfg(all(unix, feature = "std"))]
+mod session;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod sidecar;
+#[cfg(feature = "std")]
+mod signal;
+#[cfg(feature = "std")]
+mod timeout;
+#[cfg(feature = "tempfile")]
+mod unique;
+#[cfg(feature = "std")]
+mod validate;
+
+#[cfg(feature = "std")]
+pub use acquired::Acquired;
+#[cfg(feature = "std")]
+pub use batch::LockState;
+#[cfg(feature = "std")]
+pub use builder::Builder;
+#[cfg(feature = "std")]
+pub use cache::CachedChecker;
+#[cfg(all(unix, feature = "signal-cleanup"))]
+pub use cleanup::SignalCleanup;
+#[cfg(feature = "status")]
+pub use describe::Status;
+pub use error::PidFileError;
+#[cfg(feature = "std")]
+pub use liveness::{
+    check_pid, check_pid_with_signal, HealthMode, Liveness, RemoteLiveness, UnknownRemote,
+};
+#[cfg(feature = "std")]
+pub use lost::LockLost;
+#[cfg(feature = "std")]
+pub use multi::MultiPidFile;
+#[cfg(feature = "std")]
+pub use observer::Observer;
+#[cfg(all(unix, feature = "std"))]
+pub use paths::default_path;
+#[cfg(feature = "std")]
+pub use pidfile::{lock, unlock, PidFile};
+#[cfg(all(feature = "std", any(test, feature = "test-util")))]
+pub use registry::clear_registry;
+#[cfg(feature = "std")]
+pub use renewal::LeaseRenewal;
+#[cfg(feature = "std")]
+pub use repair::Repair;
+#[cfg(feature = "std")]
+pub use validate::ValidationError;
+
+#[cfg(feature = "std")]
+pub(crate) use pidfile::{
+    contents_owner, current_pid, hostname, info_owner, is_invalid, lease_expired, parent_pid,
+    pid_file_owner, pid_in_use, reclaim, CheckPolicy,
+};
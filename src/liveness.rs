@@ -0,0 +1,189 @@
+//! Checking whether the process which owns a PID file is still running.
+
+use std::fmt;
+use std::io;
+
+/// The outcome of checking whether a process is still running.
+///
+/// This distinguishes the different ways that `kill(pid, 0)` can fail, so that
+/// callers can decide for themselves how to treat each case.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Liveness {
+    /// The process exists, and we are allowed to signal it.
+    Alive,
+
+    /// No process with this PID exists (`ESRCH`).
+    Dead,
+
+    /// A process with this PID exists, but we are not allowed to signal it (`EPERM`).
+    ///
+    /// This usually means the process is owned by another user.
+    PermissionDenied,
+
+    /// The check failed for some other reason.
+    ///
+    /// When the check was a system call, the error carries its `errno`, which is available
+    /// from [`io::Error::raw_os_error`] even when it maps to a generic [`io::ErrorKind`].
+    Unknown(io::Error),
+}
+
+impl Liveness {
+    /// Whether this outcome indicates that a process with the PID exists.
+    ///
+    /// Both [`Liveness::Alive`] and [`Liveness::PermissionDenied`] indicate that
+    /// the process exists.
+    pub fn is_alive(&self) -> bool {
+        matches!(self, Liveness::Alive | Liveness::PermissionDenied)
+    }
+}
+
+/// Checks whether a process on another host is still running.
+///
+/// Implement this to make PID files on shared storage (e.g. NFS) safe to use from multiple
+/// hosts, for example by querying an agent on the remote host, and pass it to
+/// [`Builder::remote_liveness`](crate::Builder::remote_liveness).
+pub trait RemoteLiveness: Send + Sync {
+    /// Check whether the process with the given PID is still running on `host`.
+    fn is_alive(&self, host: &str, pid: libc::pid_t) -> Liveness;
+}
+
+impl fmt::Debug for dyn RemoteLiveness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteLiveness").finish_non_exhaustive()
+    }
+}
+
+/// A [`RemoteLiveness`] which can't check any remote hosts.
+///
+/// This always reports [`Liveness::Unknown`], so PID files from other hosts are never
+/// treated as stale.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UnknownRemote;
+
+impl RemoteLiveness for UnknownRemote {
+    fn is_alive(&self, host: &str, pid: libc::pid_t) -> Liveness {
+        Liveness::Unknown(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unable to check PID {pid} on remote host {host}"),
+        ))
+    }
+}
+
+/// How an application health check combines with the operating system's view of liveness.
+///
+/// See [`Builder::health_check`](crate::Builder::health_check).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthMode {
+    /// The owner is treated as dead when either the operating system reports that it is no
+    /// longer running, or the health check reports that it is unhealthy. This reclaims PID
+    /// files from processes which are running but hung.
+    Either,
+
+    /// The owner is treated as dead only when the operating system reports that it is no
+    /// longer running, and the health check also reports that it is unhealthy. This guards
+    /// against reclaiming PID files when the operating system's view can't be trusted, e.g.
+    /// across PID namespaces.
+    Both,
+}
+
+/// Check whether the process with the given PID is still running.
+///
+/// This sends signal `0` to the process, which performs error checking
+/// without actually delivering a signal.
+///
+/// PID 1 is always reported as [`Liveness::Alive`] without being signalled. Every PID
+/// namespace has an init process as PID 1 for as long as the namespace exists (e.g. the
+/// main process of a container), but signals to it may be restricted, so `kill` can't be
+/// relied on to report it.
+pub fn check_pid(pid: libc::pid_t) -> Liveness {
+    if pid == INIT_PID {
+        return Liveness::Alive;
+    }
+
+    check_pid_with_signal(pid, 0)
+}
+
+/// The PID of the init process, which is running for as long as its PID namespace exists.
+const INIT_PID: libc::pid_t = 1;
+
+/// Check whether the process with the given PID is still running, by sending it `signal`.
+///
+/// Unlike [`check_pid`], a non-zero signal is actually delivered to the process
+/// if it exists, so callers should choose a signal which the process handles or
+/// ignores. This is useful to verify that we are permitted to send a specific
+/// signal to the process. An invalid signal number is reported as
+/// [`Liveness::Unknown`].
+///
+/// Zero and negative PIDs name process groups (or every process) rather than a single
+/// process, so they are never signalled, and are reported as [`Liveness::Unknown`] with an
+/// error of kind [`io::ErrorKind::InvalidInput`].
+pub fn check_pid_with_signal(pid: libc::pid_t, signal: libc::c_int) -> Liveness {
+    if pid <= 0 {
+        return Liveness::Unknown(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("PID {pid} is not positive, so it names a process group"),
+        ));
+    }
+
+    // SAFETY: I dunno? Libc is probably fine.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::kill(pid, signal) };
+
+    if result == 0 {
+        return Liveness::Alive;
+    }
+
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::ESRCH) => Liveness::Dead,
+        Some(libc::EPERM) => Liveness::PermissionDenied,
+        _ => Liveness::Unknown(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_init() {
+        assert!(matches!(check_pid(1), Liveness::Alive));
+    }
+
+    #[test]
+    fn test_check_pid() {
+        let pid = std::process::id() as libc::pid_t;
+        assert!(matches!(check_pid(pid), Liveness::Alive));
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let child_pid = child.id() as libc::pid_t;
+        child.wait().unwrap();
+        assert!(matches!(check_pid(child_pid), Liveness::Dead));
+    }
+
+    #[test]
+    fn test_check_non_positive_pid() {
+        for pid in [0, -1] {
+            match check_pid(pid) {
+                Liveness::Unknown(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidInput),
+                liveness => panic!("expected an unknown liveness, got {liveness:?}"),
+            }
+        }
+
+        // This would otherwise signal every process we are permitted to signal.
+        match check_pid_with_signal(-1, libc::SIGTERM) {
+            Liveness::Unknown(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidInput),
+            liveness => panic!("expected an unknown liveness, got {liveness:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check_pid_with_invalid_signal() {
+        let pid = std::process::id() as libc::pid_t;
+        match check_pid_with_signal(pid, -1) {
+            Liveness::Unknown(error) => assert_eq!(error.raw_os_error(), Some(libc::EINVAL)),
+            liveness => panic!("expected an unknown liveness, got {liveness:?}"),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Reporting held PID files which were lost to another process.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::PidFile;
+
+/// A held PID file which was found to belong to another process, passed to the callback set
+/// with [`Builder::on_lock_lost`](crate::Builder::on_lock_lost).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LockLost {
+    /// The path to the PID file.
+    pub path: PathBuf,
+
+    /// The PID of this process, which held the PID file.
+    pub pid: libc::pid_t,
+
+    /// The PID of the process which holds the PID file now.
+    pub owner: libc::pid_t,
+}
+
+/// A callback for PID files which were lost to another process.
+#[derive(Clone)]
+pub(crate) struct OnLockLost(pub(crate) Arc<dyn Fn(&LockLost) + Send + Sync>);
+
+impl fmt::Debug for OnLockLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnLockLost").finish_non_exhaustive()
+    }
+}
+
+impl PidFile {
+    /// Report that this PID file now belongs to `owner`.
+    ///
+    /// The event is emitted with the target `pidfile::lost`, so that subscribers can alert
+    /// on it, or count it as a metric, without matching on the message.
+    pub(crate) fn lock_lost(&self, owner: libc::pid_t) {
+        crate::diag::error!(path=%self.path.display(), pid = self.pid, owner = owner, "Lost PID file {path} to PID {owner}", path = self.path.display());
+
+        if let Some(callback) = &self.on_lock_lost {
+            (callback.0)(&LockLost {
+                path: self.path.clone(),
+                pid: self.pid,
+                owner,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+    use std::sync::Mutex;
+
+    use crate::{Builder, Repair};
+
+    #[test]
+    fn test_on_lock_lost() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("lost.pid");
+        let lost = Arc::new(Mutex::new(Vec::new()));
+
+        let mut pid_file = {
+            let lost = Arc::clone(&lost);
+            Builder::new(&path)
+                .on_lock_lost(move |event| lost.lock().unwrap().push(event.clone()))
+                .create()
+                .unwrap()
+        };
+        pid_file.heartbeat().unwrap();
+        assert!(lost.lock().unwrap().is_empty());
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let owner = child.id() as libc::pid_t;
+        std::fs::write(&path, owner.to_string()).unwrap();
+
+        let (repair, error) = silenced(|| {
+            (
+                pid_file.verify_and_repair().unwrap(),
+                pid_file.heartbeat().unwrap_err(),
+            )
+        });
+        assert_eq!(repair, Repair::Taken(owner));
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+
+        let expected = LockLost {
+            path: path.clone(),
+            pid: std::process::id() as libc::pid_t,
+            owner,
+        };
+        assert_eq!(*lost.lock().unwrap(), vec![expected.clone(), expected]);
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        std::fs::write(&path, std::process::id().to_string()).unwrap();
+        drop(pid_file);
+    }
+}
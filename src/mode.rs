@@ -0,0 +1,84 @@
+//! The permissions which PID files are created with, independent of the `umask`.
+
+use std::fs::{File, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The permissions of new PID files: readable by everyone, and writable by the owner.
+pub(crate) const DEFAULT_MODE: u32 = 0o644;
+
+/// Set the permissions of a newly created file to `mode`, which the `umask` may have narrowed.
+///
+/// This does nothing on platforms without Unix permissions.
+pub(crate) fn set_mode(file: &File, mode: u32) -> Result<(), io::Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = (file, mode);
+        Ok(())
+    }
+}
+
+/// The permissions of an existing file, to carry over to the file which replaces it.
+pub(crate) fn mode_of(metadata: Result<Metadata, io::Error>) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.map_or(DEFAULT_MODE, |metadata| {
+            metadata.permissions().mode() & 0o7777
+        })
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        DEFAULT_MODE
+    }
+}
+
+/// Create the temporary file `tmp`, which is written and then renamed over a PID file.
+///
+/// Temporary files have predictable names, so this never opens an existing file at `tmp`,
+/// nor follows a symlink there. Anything left behind at `tmp` is removed (a symlink itself,
+/// rather than its target) before the temporary file is created again.
+pub(crate) fn create_tmp(tmp: &Path) -> Result<File, io::Error> {
+    let open = || {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.custom_flags(libc::O_NOFOLLOW);
+        }
+        options.open(tmp)
+    };
+
+    match open() {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(tmp)?;
+            open()
+        }
+        result => result,
+    }
+}
+
+/// Atomically replace the file at `path` with `contents`, by writing `tmp` and renaming it.
+///
+/// The replacement keeps the permissions of the file it replaces.
+pub(crate) fn replace(path: &Path, tmp: &Path, contents: &str) -> Result<(), io::Error> {
+    let mode = mode_of(std::fs::metadata(path));
+    let result = create_tmp(tmp).and_then(|mut file| {
+        set_mode(&file, mode)?;
+        file.write_all(contents.as_bytes())?;
+        std::fs::rename(tmp, path)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(tmp);
+    }
+    result
+}
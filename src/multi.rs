@@ -0,0 +1,152 @@
+//! Acquiring several PID files at once.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::registry::registry_key;
+use crate::PidFile;
+
+/// A guard holding several PID files, acquired all-or-nothing by [`PidFile::new_multi`].
+///
+/// Dropping this guard releases all of the PID files.
+#[derive(Debug)]
+pub struct MultiPidFile {
+    pidfiles: Vec<PidFile>,
+}
+
+impl MultiPidFile {
+    /// The held PID files, in the order they were acquired.
+    pub fn pidfiles(&self) -> &[PidFile] {
+        &self.pidfiles
+    }
+
+    /// The paths of the held PID files, in the order they were acquired.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.pidfiles.iter().map(|pidfile| pidfile.path.as_path())
+    }
+}
+
+impl Drop for MultiPidFile {
+    fn drop(&mut self) {
+        // Release in the reverse of the order of acquisition.
+        while let Some(pidfile) = self.pidfiles.pop() {
+            drop(pidfile);
+        }
+    }
+}
+
+impl PidFile {
+    /// Create PID files at each of the given paths, acquiring all of them or none of them.
+    ///
+    /// # Ordering
+    ///
+    /// The PID files are always acquired in a total order: the paths are sorted by their
+    /// canonical form (absolute, with symlinks in their parent directories resolved), and
+    /// paths which refer to the same PID file are de-duplicated, keeping the first. So every
+    /// thread and process acquires a given set of PID files in the same order, however the
+    /// paths are spelled, and callers which need overlapping sets of PID files, and which
+    /// retry until they have all of them, can't deadlock. A path whose parent directory
+    /// doesn't exist is ordered by its absolute form.
+    ///
+    /// If any of the PID files can't be created, the ones which were already acquired
+    /// are released before the error is returned.
+    pub fn new_multi(paths: &[PathBuf]) -> Result<MultiPidFile, io::Error> {
+        let mut paths = paths
+            .iter()
+            .map(|path| Ok((registry_key(path)?, path)))
+            .collect::<Result<Vec<_>, io::Error>>()?;
+        // The sort is stable, so the first of several paths to the same PID file is kept.
+        paths.sort_by(|(a, _), (b, _)| a.cmp(b));
+        paths.dedup_by(|(a, _), (b, _)| a == b);
+
+        let mut pidfiles = Vec::with_capacity(paths.len());
+        for (_, path) in paths {
+            match PidFile::new(path) {
+                Ok(pidfile) => pidfiles.push(pidfile),
+                Err(error) => {
+                    crate::diag::debug!(path=%path.display(), "Releasing {count} PID files after failing to lock {path}", count = pidfiles.len(), path = path.display());
+                    drop(MultiPidFile { pidfiles });
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(MultiPidFile { pidfiles })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_new_multi_rollback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.pid");
+        let b = tmp.path().join("b.pid");
+        let c = tmp.path().join("c.pid");
+
+        let held = PidFile::new(&b).unwrap();
+        let error =
+            silenced(|| PidFile::new_multi(&[c.clone(), a.clone(), b.clone()]).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(!a.exists());
+        assert!(!c.exists());
+        drop(held);
+
+        let multi = PidFile::new_multi(&[c.clone(), a.clone(), b.clone()]).unwrap();
+        assert_eq!(
+            multi.paths().collect::<Vec<_>>(),
+            vec![a.as_path(), b.as_path(), c.as_path()]
+        );
+        drop(multi);
+        assert!(!a.exists() && !b.exists() && !c.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_new_multi_ordering() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a.pid");
+        let b = tmp.path().join("b.pid");
+        let c = tmp.path().join("c.pid");
+        // The same PID file as `b`, through a symlink which sorts before `a`.
+        std::os::unix::fs::symlink(tmp.path(), tmp.path().join("0")).unwrap();
+        let alias = tmp.path().join("0").join("b.pid");
+
+        let multi = PidFile::new_multi(&[c.clone(), alias.clone(), a.clone(), b.clone()]).unwrap();
+        assert_eq!(
+            multi.paths().collect::<Vec<_>>(),
+            vec![a.as_path(), alias.as_path(), c.as_path()]
+        );
+        drop(multi);
+
+        // Two threads repeatedly acquire overlapping sets, listed in different orders.
+        let (done, finished) = std::sync::mpsc::channel();
+        for paths in [vec![a.clone(), b.clone(), c.clone()], vec![c, alias, a]] {
+            let done = done.clone();
+            std::thread::spawn(move || {
+                for _ in 0..50 {
+                    let multi = loop {
+                        match silenced(|| PidFile::new_multi(&paths)) {
+                            Ok(multi) => break multi,
+                            Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+                                std::thread::yield_now();
+                            }
+                            Err(error) => panic!("unexpected error: {error}"),
+                        }
+                    };
+                    drop(multi);
+                }
+                done.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..2 {
+            finished
+                .recv_timeout(std::time::Duration::from_secs(30))
+                .expect("acquiring overlapping sets of PID files deadlocked");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! A non-owning handle for watching a PID file held by another process.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::batch::check;
+use crate::contents::{from_timestamp, parse_pid, Contents};
+use crate::{CheckPolicy, LockState, PidFile};
+
+/// A handle for watching a PID file, which never modifies or removes it.
+///
+/// Unlike a [`PidFile`], an `Observer` doesn't hold the lock, and has no side effects when
+/// it is dropped, so monitoring code can keep one around to query a PID file repeatedly
+/// without any risk of removing it. Create one with [`PidFile::observe`].
+///
+/// # Example
+///
+/// ```rust
+/// use pidfile::PidFile;
+///
+/// # fn main() -> Result<(), std::io::Error> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("observed.pid");
+/// let observer = PidFile::observe(&path);
+/// assert!(!observer.is_locked()?);
+///
+/// let pidfile = PidFile::new(&path)?;
+/// assert_eq!(observer.read_pid()?, Some(std::process::id() as libc::pid_t));
+/// # drop(pidfile);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Observer {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Watch the PID file at this path, without holding it.
+    pub fn observe(path: impl Into<PathBuf>) -> Observer {
+        Observer { path: path.into() }
+    }
+}
+
+impl Observer {
+    /// The path to the PID file being watched.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The state of the PID file, as reported by [`PidFile::check_many`].
+    pub fn state(&self) -> Result<LockState, io::Error> {
+        check(&self.path, &mut String::new(), &CheckPolicy::default())
+    }
+
+    /// Whether the PID file is held, as reported by [`PidFile::is_locked`].
+    pub fn is_locked(&self) -> Result<bool, io::Error> {
+        PidFile::is_locked(&self.path)
+    }
+
+    /// The PID stored in the PID file, whether or not that process is still running.
+    ///
+    /// Returns `Ok(None)` when there is no PID file, or when it is empty, and an error with
+    /// [`io::ErrorKind::InvalidData`] when it doesn't contain a valid PID.
+    pub fn read_pid(&self) -> Result<Option<libc::pid_t>, io::Error> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(info) if info.trim().is_empty() => Ok(None),
+            Ok(info) => parse_pid(&self.path, &info).map(Some),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// How long the PID file has been held, with the same semantics as [`PidFile::age`].
+    pub fn age(&self) -> Result<Duration, io::Error> {
+        let info = std::fs::read_to_string(&self.path)?;
+        let contents = Contents::parse(&self.path, &info)?;
+        let started = match contents.field("started") {
+            Some(started) => from_timestamp(started)?,
+            None => std::fs::metadata(&self.path)?.modified()?,
+        };
+
+        Ok(SystemTime::now()
+            .duration_since(started)
+            .unwrap_or(Duration::ZERO))
+    }
+
+    /// Describe the state of the PID file, as [`PidFile::describe`] does.
+    pub fn describe(&self) -> Result<String, io::Error> {
+        PidFile::describe(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_observer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("observer.pid");
+        let observer = PidFile::observe(&path);
+        assert_eq!(observer.state().unwrap(), LockState::Missing);
+        assert_eq!(observer.read_pid().unwrap(), None);
+        assert!(observer.age().is_err());
+
+        let pid_file = PidFile::new(&path).unwrap();
+        let pid = std::process::id() as libc::pid_t;
+        assert_eq!(observer.state().unwrap(), LockState::Held(pid));
+        assert_eq!(observer.read_pid().unwrap(), Some(pid));
+        assert!(observer.age().unwrap() < Duration::from_secs(60));
+
+        drop(observer.clone());
+        assert!(path.exists());
+        drop(pid_file);
+        assert!(!observer.is_locked().unwrap());
+    }
+}
@@ -0,0 +1,231 @@
+//! Conventional locations for PID files.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{PidFile, PidFileError};
+
+/// The conventional PID file path for a system service, as a `&'static str`.
+///
+/// This expands to `/run/<app>.pid` at compile time, so it can be used in `const`
+/// items. For a path which also suits unprivileged processes, use [`default_path`].
+///
+/// # Example
+///
+/// ```rust
+/// const PID_FILE: &str = pidfile::default_path!("myapp");
+/// assert_eq!(PID_FILE, "/run/myapp.pid");
+/// ```
+#[macro_export]
+macro_rules! default_path {
+    ($app:literal) => {
+        concat!("/run/", $app, ".pid")
+    };
+}
+
+/// The conventional PID file path for an application named `app`.
+///
+/// This is `<dir>/<app>.pid`, where `<dir>` is the first of:
+///
+/// 1. `$XDG_RUNTIME_DIR`, when it is set (e.g. for user services under systemd).
+/// 2. `/run`, when running as root.
+/// 3. The system temporary directory (see [`std::env::temp_dir`]).
+///
+/// The directory is not created. Use [`Builder::create_dirs`](crate::Builder::create_dirs)
+/// if it may not exist.
+///
+/// # Example
+///
+/// ```rust
+/// let path = pidfile::default_path("myapp");
+/// assert!(path.ends_with("myapp.pid"));
+/// ```
+pub fn default_path(app: &str) -> PathBuf {
+    // SAFETY: `geteuid` is always successful.
+    #[allow(unsafe_code)]
+    let root = unsafe { libc::geteuid() } == 0;
+
+    runtime_dir(std::env::var_os("XDG_RUNTIME_DIR"), root).join(format!("{app}.pid"))
+}
+
+impl PidFile {
+    /// Ensure that only one instance of the application named `app` is running.
+    ///
+    /// This is the most common use of PID files in one call: it creates the PID file at
+    /// [`default_path`] for `app`, and returns the guard, which must be kept alive for as
+    /// long as the application runs. With the `signal-cleanup` feature, it also installs a
+    /// `SignalCleanup` for the rest of the process, so the PID file is removed when the
+    /// process is terminated by `SIGINT` or `SIGTERM` (unless one is already installed).
+    ///
+    /// If another instance is running, this returns an error with
+    /// [`io::ErrorKind::AddrInUse`]. Use [`PidFile::builder`] for more control.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use pidfile::PidFile;
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     let _instance = PidFile::assert_single_instance("myapp")?;
+    ///     // Do stuff
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn assert_single_instance(app: &str) -> Result<PidFile, io::Error> {
+        #[cfg(feature = "signal-cleanup")]
+        match crate::SignalCleanup::install() {
+            // The handlers stay installed for the rest of the process.
+            Ok(cleanup) => std::mem::forget(cleanup),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+
+        PidFile::new(default_path(app))
+    }
+
+    /// Create a PID file at `path`, which must be within the directory `base`.
+    ///
+    /// This guards against configuration which points the PID file somewhere it shouldn't
+    /// be, e.g. `../../etc/myapp.pid`. A relative `path` is taken relative to `base`. The
+    /// directory containing the PID file is canonicalized, resolving `..` and symlinks, and
+    /// must be `base` or a directory inside it. If a file already exists at `path` and is a
+    /// symlink, it must also resolve to a file inside `base`. Paths which escape `base`
+    /// return an error with [`io::ErrorKind::PermissionDenied`], carrying
+    /// [`PidFileError::OutsideDirectory`]. Both directories must already exist.
+    ///
+    /// The path is checked once, before the PID file is created as with [`PidFile::new`],
+    /// so this doesn't protect against a process which can rename or replace directories
+    /// inside `base` while the PID file is created.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use pidfile::PidFile;
+    ///
+    /// # fn main() -> Result<(), std::io::Error> {
+    /// # let tmp = tempfile::tempdir()?;
+    /// # let base = tmp.path();
+    /// let pidfile = PidFile::new_checked("myapp.pid", base)?;
+    ///
+    /// let error = PidFile::new_checked("../myapp.pid", base).unwrap_err();
+    /// assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+    /// # drop(pidfile);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_checked(
+        path: impl AsRef<Path>,
+        base: impl AsRef<Path>,
+    ) -> Result<PidFile, io::Error> {
+        PidFile::new(checked_path(path.as_ref(), base.as_ref())?)
+    }
+}
+
+/// Resolve `path` within `base`, failing if it escapes `base` through `..` or symlinks.
+fn checked_path(path: &Path, base: &Path) -> Result<PathBuf, io::Error> {
+    let base = base.canonicalize()?;
+    let outside = || -> io::Error {
+        crate::diag::error!(path=%path.display(), "PID File {path} is outside the allowed directory {dir}", path = path.display(), dir = base.display());
+        PidFileError::OutsideDirectory {
+            path: path.display().to_string(),
+            dir: base.display().to_string(),
+        }
+        .into()
+    };
+
+    let joined = base.join(path);
+    let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+        return Err(outside());
+    };
+    let resolved = parent.canonicalize()?.join(name);
+    if !resolved.starts_with(&base) {
+        return Err(outside());
+    }
+
+    // An existing symlink would be followed when the PID file is read or replaced.
+    let symlink = std::fs::symlink_metadata(&resolved)
+        .is_ok_and(|metadata| metadata.file_type().is_symlink());
+    if symlink
+        && !resolved
+            .canonicalize()
+            .is_ok_and(|target| target.starts_with(&base))
+    {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+/// The directory for PID files, given the value of `$XDG_RUNTIME_DIR` and whether we are root.
+fn runtime_dir(xdg_runtime_dir: Option<OsString>, root: bool) -> PathBuf {
+    match xdg_runtime_dir {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if root => PathBuf::from("/run"),
+        _ => std::env::temp_dir(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_runtime_dir() {
+        assert_eq!(
+            runtime_dir(Some("/run/user/1000".into()), false),
+            PathBuf::from("/run/user/1000")
+        );
+        assert_eq!(runtime_dir(Some("".into()), true), PathBuf::from("/run"));
+        assert_eq!(runtime_dir(None, false), std::env::temp_dir());
+        assert_eq!(default_path!("myapp"), "/run/myapp.pid");
+    }
+
+    #[test]
+    fn test_new_checked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let base = tmp.path().join("run");
+        let outside = tmp.path().join("elsewhere");
+        std::fs::create_dir(&base).unwrap();
+        std::fs::create_dir(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, base.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("target.pid"), base.join("escape.pid")).unwrap();
+
+        let pid_file = PidFile::new_checked("app.pid", &base).unwrap();
+        assert_eq!(
+            pid_file.path(),
+            base.canonicalize().unwrap().join("app.pid")
+        );
+        drop(pid_file);
+
+        let escapes = [
+            PathBuf::from("../elsewhere/app.pid"),
+            PathBuf::from("link/app.pid"),
+            PathBuf::from("escape.pid"),
+            PathBuf::from(".."),
+            outside.join("app.pid"),
+        ];
+        for path in escapes {
+            let error = silenced(|| PidFile::new_checked(&path, &base).unwrap_err());
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied, "{path:?}");
+            assert!(matches!(
+                error.get_ref().unwrap().downcast_ref(),
+                Some(PidFileError::OutsideDirectory { .. })
+            ));
+        }
+        assert_eq!(std::fs::read_dir(&outside).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_assert_single_instance() {
+        let app = format!("pidfile-single-instance-{}", std::process::id());
+        let instance = PidFile::assert_single_instance(&app).unwrap();
+        assert_eq!(instance.path(), default_path(&app));
+
+        let error = silenced(|| PidFile::assert_single_instance(&app).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(instance);
+        assert!(!default_path(&app).exists());
+    }
+}
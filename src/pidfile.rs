@@ -0,0 +1,1915 @@
+//! The [`PidFile`] lock guard, and the logic for checking and reclaiming PID files.
+
+use std::collections::BTreeMap;
+use std::io;
+#[cfg(unix)]
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::boot::previous_boot;
+use crate::contents::{from_timestamp, to_timestamp, Contents};
+use crate::quiet::in_use;
+use crate::registry::Registration;
+use crate::shared;
+use crate::sidecar::{SidecarLock, LOCK_WAIT};
+#[cfg(unix)]
+use crate::{at, fcntl};
+use crate::{
+    builder, check_pid, timeout, Builder, HealthMode, Liveness, PidFileError, RemoteLiveness,
+    UnknownRemote,
+};
+
+/// A PID file is a file that contains the PID of a process. It is used to
+/// prevent multiple instances of a process from running at the same time,
+/// or to provide a lock for a resource which should only be accessed by one
+/// process at a time.
+///
+/// # Ownership
+///
+/// A `PidFile` is the unique owner of the lock, and removes the PID file when it is
+/// dropped. For that reason it is not [`Clone`]: two clones would both try to remove
+/// the same file, and the lock would be released as soon as the first was dropped.
+///
+/// ```compile_fail
+/// fn assert_clone<T: Clone>() {}
+/// assert_clone::<pidfile::PidFile>();
+/// ```
+///
+/// To share a lock between threads or components, wrap it in an [`Arc`]. The lock is
+/// held until the last clone of the `Arc` is dropped.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use pidfile::PidFile;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("shared.pid");
+/// let pidfile = Arc::new(PidFile::new(&path)?);
+///
+/// let worker = {
+///     let pidfile = Arc::clone(&pidfile);
+///     std::thread::spawn(move || {
+///         // The lock is held for as long as this thread holds its clone.
+///         drop(pidfile);
+///     })
+/// };
+///
+/// worker.join().unwrap();
+/// assert!(PidFile::is_locked(&path)?);
+/// drop(pidfile);
+/// assert!(!PidFile::is_locked(&path)?);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Equality
+///
+/// `PidFile`s compare equal, and hash the same, when they are at the same path, so they can
+/// be kept in a [`HashSet`](std::collections::HashSet) or used as
+/// [`HashMap`](std::collections::HashMap) keys to track held locks by location. This is
+/// equality of paths, not identity: a `PidFile` compares equal to a later `PidFile` at
+/// the same path. Paths are made absolute against the working directory when the PID file
+/// is created, and symbolic links in their parent directory are resolved. PID files created
+/// with [`PidFile::new_at`] are compared by their path relative to their directory.
+///
+/// Within a process, only one `PidFile` can be held for each file. On Unix, this compares
+/// the device and inode of the file, so a second `PidFile` at a symlink or hard link to a
+/// held PID file fails with [`io::ErrorKind::AddrInUse`], rather than appearing to be a
+/// distinct lock. Other platforms only compare paths.
+#[derive(Debug)]
+pub struct PidFile {
+    pub(crate) path: PathBuf,
+    pub(crate) pid: libc::pid_t,
+
+    /// The directory which `path` is relative to, when created with [`PidFile::new_at`].
+    #[cfg(unix)]
+    pub(crate) dir: Option<OwnedFd>,
+
+    /// The open PID file, which holds an `fcntl` lock, when created with [`Builder::fcntl_lock`],
+    /// or which was passed in with [`PidFile::from_fd`] or [`PidFile::from_file`].
+    ///
+    /// This is closed after the PID file is removed, which releases the lock.
+    #[cfg(unix)]
+    pub(crate) lock_file: Option<std::fs::File>,
+
+    /// Whether `lock_file` was passed in with [`PidFile::from_fd`], in which case it is
+    /// truncated rather than removed.
+    #[cfg(unix)]
+    pub(crate) passed_fd: bool,
+
+    /// Whether this process is one of several shared holders, from [`PidFile::new_shared`].
+    pub(crate) shared: bool,
+
+    /// The lock directory containing this PID file, when created with [`Builder::lock_dir`].
+    pub(crate) lock_dir: Option<PathBuf>,
+
+    /// The PID of the process which left behind the stale PID file reclaimed to create this
+    /// one, if there was one.
+    pub(crate) reclaimed: Option<libc::pid_t>,
+
+    /// The contents which this process wrote to the PID file when it was created, which
+    /// [`PidFile::verify_and_repair`] writes back.
+    pub(crate) written: String,
+
+    /// Directories created to hold this PID file, which should be removed on drop.
+    pub(crate) created_dirs: Vec<PathBuf>,
+
+    /// The path of this PID file as published to the signal handler, when it is installed.
+    #[cfg(all(unix, feature = "signal-cleanup"))]
+    pub(crate) published: Option<crate::cleanup::Published>,
+
+    /// How long to wait for the PID file to be removed, set by [`Builder::io_timeout`].
+    pub(crate) io_timeout: Option<Duration>,
+
+    /// Whether to keep the PID file on disk when it is dropped, set by [`PidFile::into_path`].
+    pub(crate) keep: bool,
+
+    /// Whether to remove the PID file when it is dropped while the thread is panicking.
+    pub(crate) cleanup_on_panic: bool,
+
+    /// The callback for when the PID file is lost, set by [`Builder::on_lock_lost`].
+    pub(crate) on_lock_lost: Option<crate::lost::OnLockLost>,
+
+    /// The entry for this PID file in the in-process registry.
+    ///
+    /// This is declared last so that it is released after the PID file is removed.
+    pub(crate) _registration: Option<Registration>,
+}
+
+/// Check if the process with the given PID is still running.
+///
+/// Processes which exist but which we are not permitted to signal are treated as running.
+pub(crate) fn pid_in_use(pid: libc::pid_t) -> Result<bool, io::Error> {
+    liveness_in_use(pid, check_pid(pid))
+}
+
+/// Decide whether the process with the given PID is still running, given its liveness.
+fn liveness_in_use(pid: libc::pid_t, liveness: Liveness) -> Result<bool, io::Error> {
+    let _span = crate::diag::trace_span!("liveness", %pid).entered();
+    match liveness {
+        Liveness::Alive => {
+            in_use!(debug, %pid, "PID {pid} is still running", pid = pid);
+            // This PID still exists, so the pid file is valid.
+            Ok(true)
+        }
+        Liveness::PermissionDenied => {
+            in_use!(debug, %pid, "PID {pid} is still running as another user", pid = pid);
+            Ok(true)
+        }
+        Liveness::Dead => {
+            crate::diag::debug!(%pid, "PID {pid} is no longer running", pid = pid);
+            Ok(false)
+        }
+        Liveness::Unknown(error) => {
+            // Many `errno` values map to the same `ErrorKind`, so always record the number.
+            crate::diag::warn!(%pid, errno = ?error.raw_os_error(), "Unknown error checking PID {pid}: {error}", pid = pid);
+            Err(error)
+        }
+    }
+}
+
+/// A parser for the PID in the contents of a PID file.
+pub(crate) type PidParser = fn(&str) -> Result<libc::pid_t, io::Error>;
+
+/// An application health check for the process with a PID, returning whether it is healthy.
+pub(crate) type HealthCheck = fn(libc::pid_t) -> Result<bool, io::Error>;
+
+/// Configuration for deciding whether the owner of a PID file is still running.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CheckPolicy {
+    /// Resolver for PID files which were written on other hosts.
+    pub(crate) remote: Option<Arc<dyn RemoteLiveness>>,
+
+    /// How long a PID file must be left unmodified before it can be reclaimed.
+    pub(crate) grace: Option<Duration>,
+
+    /// A custom parser for the PID in PID files with a bespoke format.
+    pub(crate) parser: Option<PidParser>,
+
+    /// An application health check, and how it combines with the operating system's view.
+    pub(crate) health: Option<(HealthCheck, HealthMode)>,
+
+    /// The instance ID of this process, for recognizing PID files left by its previous instances.
+    pub(crate) instance: Option<Arc<str>>,
+
+    /// The magic value which PID files must carry before they can be reclaimed.
+    pub(crate) magic: Option<Arc<str>>,
+}
+
+impl CheckPolicy {
+    /// Refuse to reclaim an existing PID file which doesn't carry the magic value, since it
+    /// may be an unrelated file.
+    ///
+    /// Empty files are still reclaimed, since they are left by a process which crashed
+    /// before writing its PID file.
+    fn check_magic(
+        &self,
+        path: &Path,
+        info: &str,
+        owner: Result<Owner, io::Error>,
+    ) -> Result<Owner, io::Error> {
+        let Some(magic) = self.magic.as_deref() else {
+            return owner;
+        };
+
+        let reclaimable = match &owner {
+            Ok(owner) => matches!(owner, Owner::Dead(_) | Owner::Previous(_)),
+            Err(error) => is_invalid(error),
+        };
+        if reclaimable && !has_magic(info, magic) {
+            crate::diag::error!(path=%path.display(), "Refusing to replace {path}, which doesn't look like a PID file written by this application", path = path.display());
+            return Err(PidFileError::Unrecognized {
+                path: path.display().to_string(),
+            }
+            .into());
+        }
+        owner
+    }
+
+    /// Parse the contents of a PID file, using the custom parser if there is one.
+    fn parse(&self, path: &Path, info: &str) -> Result<Contents, io::Error> {
+        let Some(parser) = self.parser else {
+            return Contents::parse(path, info);
+        };
+
+        let pid = parser(info)?;
+        // `kill` treats zero and negative PIDs as process groups, so they can never be checked.
+        if pid <= 0 {
+            return Err(PidFileError::NonPositivePid(pid).into());
+        }
+        Ok(Contents::new(pid))
+    }
+
+    /// Check whether the owner of a PID file with these contents is still running.
+    fn liveness(&self, contents: &Contents) -> Result<Liveness, io::Error> {
+        let pid = contents.pid();
+        if let Some(host) = remote_host(contents)? {
+            crate::diag::debug!(%pid, "PID {pid} is on remote host {host}");
+            let remote = self.remote.as_deref().unwrap_or(&UnknownRemote);
+            return Ok(remote.is_alive(host, pid));
+        }
+
+        Ok(check_pid(pid))
+    }
+
+    /// Combine whether the owner of a PID file is running, according to the operating
+    /// system, with the health check, if there is one.
+    fn alive(&self, contents: &Contents, running: bool) -> Result<bool, io::Error> {
+        let Some((check, mode)) = self.health else {
+            return Ok(running);
+        };
+
+        // The health check can only reach processes on this host.
+        if remote_host(contents)?.is_some() {
+            return Ok(running);
+        }
+
+        let pid = contents.pid();
+        let alive = match mode {
+            HealthMode::Either => running && check(pid)?,
+            HealthMode::Both => running || check(pid)?,
+        };
+        if alive != running {
+            crate::diag::debug!(%pid, "PID {pid} is {state} according to its health check", state = if alive { "alive" } else { "dead" });
+        }
+        Ok(alive)
+    }
+}
+
+/// Whether the lease recorded in a PID file with these contents has expired.
+///
+/// PID files without a lease never expire.
+pub(crate) fn lease_expired(contents: &Contents) -> Result<bool, io::Error> {
+    match contents.field("expires") {
+        Some(expires) => Ok(from_timestamp(expires)? <= SystemTime::now()),
+        None => Ok(false),
+    }
+}
+
+/// The host which wrote a PID file with these contents, if it is not this one.
+fn remote_host(contents: &Contents) -> Result<Option<&str>, io::Error> {
+    match contents.field("host") {
+        Some(host) if host != hostname()? => Ok(Some(host)),
+        _ => Ok(None),
+    }
+}
+
+/// The owner of a PID file, as determined by checking whether it is still running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Owner {
+    /// There is no PID file.
+    Absent,
+
+    /// The PID file is empty, e.g. because a process crashed while writing it.
+    Empty,
+
+    /// The PID file contains the PID of this process, but was not created by it.
+    Current(libc::pid_t),
+
+    /// The PID file is held by a running process.
+    Alive(libc::pid_t),
+
+    /// The PID file was left behind by a process which is no longer running.
+    Dead(libc::pid_t),
+
+    /// The PID file was left behind by a previous instance of this process, with the same
+    /// instance ID, which is no longer running.
+    Previous(libc::pid_t),
+
+    /// The PID file was left behind by a process which is no longer running, but was
+    /// modified too recently to reclaim, and won't be reclaimable for the given duration.
+    Settling(libc::pid_t, Duration),
+}
+
+impl Owner {
+    /// The PID of the owner, if it is still running.
+    pub(crate) fn alive(self) -> Option<libc::pid_t> {
+        match self {
+            Owner::Current(pid) | Owner::Alive(pid) => Some(pid),
+            _ => None,
+        }
+    }
+}
+
+/// How much longer a file modified at `modified` must settle before the end of the `grace` period.
+fn settle_remaining(modified: SystemTime, grace: Duration) -> Option<Duration> {
+    // A modification time in the future (e.g. from clock skew) has not settled at all.
+    let age = modified.elapsed().unwrap_or(Duration::ZERO);
+    grace
+        .checked_sub(age)
+        .filter(|remaining| !remaining.is_zero())
+}
+
+/// Whether a PID file is present, but has no owner to check: it is empty, contains only
+/// whitespace, or doesn't contain a valid PID (e.g. garbage, or a number out of range).
+///
+/// Such a PID file is never held. [`PidFile::is_locked`] reports it as unlocked and leaves
+/// it alone, while [`PidFile::new`] reclaims it. Both decide with this function, so that
+/// they always agree.
+pub(crate) fn is_unusable(owner: &Result<Owner, io::Error>) -> bool {
+    match owner {
+        Ok(owner) => *owner == Owner::Empty,
+        Err(error) => is_invalid(error),
+    }
+}
+
+/// Whether the PID file at `path` is in use, given the result of checking its owner, as
+/// decided by [`PidFile::is_locked`].
+pub(crate) fn owner_locked(
+    path: &Path,
+    owner: Result<Owner, io::Error>,
+) -> Result<bool, io::Error> {
+    if is_unusable(&owner) {
+        if owner.is_err() {
+            crate::diag::warn!(path=%path.display(), "Invalid PID file at {path}", path = path.display());
+        }
+        return Ok(false);
+    }
+
+    match owner {
+        Ok(owner) => Ok(owner.alive().is_some()),
+        Err(error) => {
+            crate::diag::error!(path=%path.display(), errno = ?error.raw_os_error(), "Unable to check PID file {path}: {error}", path=path.display());
+            Err(error)
+        }
+    }
+}
+
+/// Whether checking a PID file failed because it doesn't contain a valid PID, rather than
+/// because it couldn't be read.
+pub(crate) fn is_invalid(error: &io::Error) -> bool {
+    error.kind() == io::ErrorKind::InvalidData
+}
+
+/// Get the owner of a PID file.
+pub(crate) fn pid_file_owner(path: &Path, policy: &CheckPolicy) -> Result<Owner, io::Error> {
+    contents_owner(path, std::fs::read_to_string(path), policy)
+}
+
+/// The metadata field which carries the magic value set with [`Builder::magic`].
+pub(crate) const MAGIC_FIELD: &str = "magic";
+
+/// Whether the contents of a PID file carry the magic line for `magic`.
+///
+/// This looks at the raw lines, so that it works with custom PID file formats too.
+fn has_magic(info: &str, magic: &str) -> bool {
+    info.lines()
+        .filter_map(|line| line.trim().strip_prefix(MAGIC_FIELD))
+        .any(|value| value.strip_prefix('=') == Some(magic))
+}
+
+/// Get the owner of a PID file, given the contents read from it.
+///
+/// When the policy has a grace period, a PID file with a dead owner is reported as
+/// [`Owner::Settling`] until it has gone unmodified at `path` for the whole grace period.
+pub(crate) fn contents_owner(
+    path: &Path,
+    contents: Result<String, io::Error>,
+    policy: &CheckPolicy,
+) -> Result<Owner, io::Error> {
+    match contents {
+        Ok(info) => policy.check_magic(path, &info, info_owner(path, &info, policy)),
+        Err(error) => match error.kind() {
+            io::ErrorKind::NotFound => Ok(Owner::Absent),
+            // A file which isn't UTF-8 can't carry the magic line, so it is never replaced.
+            io::ErrorKind::InvalidData => policy.check_magic(path, "", Err(error)),
+            _ => Err(error),
+        },
+    }
+}
+
+/// Get the owner of an existing PID file, given the contents read from it.
+pub(crate) fn info_owner(
+    path: &Path,
+    info: &str,
+    policy: &CheckPolicy,
+) -> Result<Owner, io::Error> {
+    if info.trim().is_empty() {
+        return Ok(Owner::Empty);
+    }
+
+    let contents = policy.parse(path, info)?;
+    let pid = contents.pid();
+    if lease_expired(&contents)? {
+        crate::diag::debug!(path=%path.display(), %pid, "Lease for PID file {path} has expired", path = path.display());
+    } else if remote_host(&contents)?.is_none() && previous_boot(&contents)? {
+        crate::diag::debug!(path=%path.display(), %pid, "PID file {path} is from before the last boot", path = path.display());
+    } else if pid == current_pid()? && remote_host(&contents)?.is_none() {
+        return Ok(Owner::Current(pid));
+    } else if policy.alive(
+        &contents,
+        liveness_in_use(pid, policy.liveness(&contents)?)?,
+    )? {
+        return Ok(Owner::Alive(pid));
+    } else if let Some(holder) = shared::live_holder(&contents, pid)? {
+        return Ok(Owner::Alive(holder));
+    }
+
+    // Our previous instance can't be in the middle of writing the PID file, so there is no
+    // need to wait for it to settle.
+    if policy
+        .instance
+        .as_deref()
+        .is_some_and(|instance| contents.field("instance") == Some(instance))
+    {
+        return Ok(Owner::Previous(pid));
+    }
+
+    if let Some(grace) = policy.grace {
+        let modified = std::fs::metadata(path)?.modified()?;
+        if let Some(remaining) = settle_remaining(modified, grace) {
+            return Ok(Owner::Settling(pid, remaining));
+        }
+    }
+
+    Ok(Owner::Dead(pid))
+}
+
+/// Decide what to do with an existing PID file, given the result of checking its owner.
+///
+/// Stale and invalid PID files may be replaced, which the caller does under the lock on the
+/// PID file (see [`SidecarLock::claim`]), while PID files which are in use (or which could
+/// not be checked) produce an error. Returns the PID of the process which left a stale PID
+/// file behind, if there was one.
+pub(crate) fn reclaim(
+    path: &Path,
+    owner: Result<Owner, io::Error>,
+) -> Result<Option<libc::pid_t>, io::Error> {
+    match owner {
+        Ok(Owner::Alive(pid)) => {
+            in_use!(error, path=%path.display(), %pid, "PID File {path} is held by PID {pid}", path = path.display());
+            Err(PidFileError::InUse {
+                path: path.display().to_string(),
+                pid,
+            }
+            .into())
+        }
+        Ok(Owner::Current(pid)) => {
+            // This can happen after a double initialization, or when the PID file was written
+            // before this process re-executed itself, so "held by PID {pid}" would be misleading.
+            in_use!(error, path=%path.display(), %pid, "PID File {path} already contains the PID of this process", path = path.display());
+            Err(PidFileError::HeldByThisProcess {
+                path: path.display().to_string(),
+            }
+            .into())
+        }
+        Ok(Owner::Settling(pid, remaining)) => {
+            in_use!(error, path=%path.display(), %pid, "PID File {path} was left by PID {pid} too recently to reclaim", path = path.display());
+            Err(PidFileError::Settling {
+                path: path.display().to_string(),
+                pid,
+                remaining,
+            }
+            .into())
+        }
+        Ok(Owner::Absent) => Ok(None),
+        Ok(Owner::Dead(pid)) => {
+            crate::diag::debug!(path=%path.display(), "Replacing stale PID file at {path}", path = path.display());
+            Ok(Some(pid))
+        }
+        Ok(Owner::Previous(pid)) => {
+            crate::diag::info!(path=%path.display(), %pid, "Recovered PID file {path} from an unclean shutdown of the previous instance, PID {pid}", path = path.display());
+            Ok(Some(pid))
+        }
+        Err(error) if !is_invalid(&error) => {
+            crate::diag::error!(path=%path.display(), errno = ?error.raw_os_error(), "Unable to check PID file {path}: {error}", path = path.display());
+            Err(error)
+        }
+        // What's left is unusable, as decided by `is_unusable`.
+        owner @ (Ok(Owner::Empty) | Err(_)) => {
+            if owner.is_ok() {
+                crate::diag::debug!(path=%path.display(), "Replacing empty PID file at {path}", path = path.display());
+            } else {
+                crate::diag::warn!(path=%path.display(), "Replacing invalid PID file at {path}", path = path.display());
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Get the PID of the current process.
+///
+/// Some C libraries (including glibc before 2.25) cache the result of `getpid`, and the
+/// cache can be stale in a child created with a raw `clone` or `vfork`, which would make
+/// the child believe it owns its parent's PID files. Where the `getpid` system call is
+/// available, it is made directly so that the PID is never cached.
+pub(crate) fn current_pid() -> Result<libc::pid_t, io::Error> {
+    #[cfg(test)]
+    if let Some(pid) = test::PID.with(std::cell::Cell::get) {
+        return Ok(pid);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    // SAFETY: The `getpid` system call takes no arguments, and always succeeds.
+    #[allow(unsafe_code)]
+    let pid = unsafe { libc::syscall(libc::SYS_getpid) } as libc::pid_t;
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    // SAFETY: What could go wrong?
+    #[allow(unsafe_code)]
+    let pid = unsafe { libc::getpid() };
+
+    if pid <= 0 {
+        crate::diag::error!("getpid returned a negative PID: {pid}");
+        return Err(io::Error::other("negative PID"));
+    }
+
+    Ok(pid)
+}
+
+/// Get the PID of the parent of the current process.
+#[cfg(unix)]
+pub(crate) fn parent_pid() -> libc::pid_t {
+    // SAFETY: `getppid` is always successful.
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::getppid()
+    }
+}
+
+/// Get the hostname of this machine.
+pub(crate) fn hostname() -> Result<String, io::Error> {
+    let mut buf = [0u8; 256];
+
+    // SAFETY: `buf` is valid for writes of `buf.len()` bytes.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+impl PidFile {
+    /// Create a new PID file at the given path for this process.
+    ///
+    /// If the PID file already exists, this function will check if the
+    /// PID file is still in use. If the PID file is in use, this function
+    /// will return Err(io::ErrorKind::AddrInUse). If the PID file is not
+    /// in use, it will be removed and a new PID file will be created.
+    ///
+    /// A PID file which is empty, contains only whitespace, or doesn't contain a valid PID
+    /// (including a number out of range for a PID) is never in use, so it is replaced, just
+    /// as [`PidFile::is_locked`] reports it as unlocked.
+    ///
+    /// This process keeps track of the PID files it holds, so creating a second
+    /// [`PidFile`] for a path which is already held by this process will also
+    /// return Err(io::ErrorKind::AddrInUse), rather than overwriting the first.
+    ///
+    /// On Unix, the PID file is created (or a stale one replaced) while holding a `flock` on
+    /// a lock file beside it, `.{name}.lock`, which is removed again afterwards. If another
+    /// process is creating the PID file at the same moment, this waits up to a second for
+    /// it to finish, and then returns Err(io::ErrorKind::AddrInUse). Failures to create or
+    /// lock the lock file are returned as errors.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        Builder::new(path).create()
+    }
+
+    /// Create a new PID file at the given path, waiting until `deadline` for it to be free.
+    ///
+    /// See [`Builder::create_until`] for details.
+    pub fn new_until(
+        path: impl Into<PathBuf>,
+        deadline: std::time::Instant,
+    ) -> Result<Self, io::Error> {
+        Builder::new(path).create_until(deadline)
+    }
+
+    /// Create a new PID file at the given path, waiting up to `timeout` for it to be free.
+    ///
+    /// See [`Builder::create_until`] for details.
+    pub fn new_timeout(path: impl Into<PathBuf>, timeout: Duration) -> Result<Self, io::Error> {
+        Builder::new(path).create_timeout(timeout)
+    }
+
+    /// Create a new PID file at the given path, making up to `attempts` attempts, and waiting
+    /// `delay` plus a random delay of up to `jitter` between them.
+    ///
+    /// The random delays keep processes which start at the same moment from retrying in
+    /// lockstep. See [`Builder::retry`] and [`Builder::retry_jitter`] for details.
+    pub fn new_with_retries_and_jitter(
+        path: impl Into<PathBuf>,
+        attempts: u32,
+        delay: Duration,
+        jitter: Duration,
+    ) -> Result<Self, io::Error> {
+        Builder::new(path)
+            .retry(attempts, delay)
+            .retry_jitter(jitter)
+            .create()
+    }
+
+    /// Attempt to create a new PID file at the given path, continuing without a lock if
+    /// the environment doesn't allow one.
+    ///
+    /// See [`Builder::create_tolerant`] for the errors which produce `Ok(None)`.
+    pub fn new_tolerant(path: impl Into<PathBuf>) -> Result<Option<Self>, io::Error> {
+        Builder::new(path).create_tolerant()
+    }
+
+    /// Create a new PID file for this process at the path in the environment variable `var`.
+    ///
+    /// This is for applications which are configured through the environment, e.g. in
+    /// containers. Returns an error with [`io::ErrorKind::InvalidInput`], naming the
+    /// variable, if it is unset or empty. Otherwise, this behaves like [`PidFile::new`].
+    pub fn from_env(var: &str) -> Result<Self, io::Error> {
+        PidFile::new(env_path(var, std::env::var_os(var))?)
+    }
+
+    /// Create a new PID file at the given path for this process, and return the open file.
+    ///
+    /// See [`Builder::create_with_file`] for details.
+    pub fn new_with_file(path: impl Into<PathBuf>) -> Result<(Self, std::fs::File), io::Error> {
+        Builder::new(path).create_with_file()
+    }
+
+    /// The path to this PID file.
+    ///
+    /// For PID files created with [`PidFile::new_at`], this is relative to the directory
+    /// they were created in. For lock directories, this is the PID file inside the lock
+    /// directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The PID of the process which left behind the stale PID file that was reclaimed to
+    /// create this one, e.g. to log it once logging has been set up.
+    ///
+    /// This is `None` when there was no PID file, or when it was empty or invalid, since
+    /// then there was no previous owner to report.
+    pub fn reclaimed_pid(&self) -> Option<libc::pid_t> {
+        self.reclaimed
+    }
+
+    /// Consume this [`PidFile`], keeping the file on disk and returning its path.
+    ///
+    /// After this call, the file is no longer managed: it is not removed when the
+    /// returned value is dropped, and it is no longer considered held by this process,
+    /// so another [`PidFile`] can be created at the same path. Any parent directories
+    /// created by [`Builder::create_dirs`] are kept as well. The path is the same as
+    /// [`PidFile::path`].
+    pub fn into_path(mut self) -> PathBuf {
+        self.keep = true;
+        std::mem::take(&mut self.path)
+    }
+
+    /// Move this PID file to `new_path`, keeping it held throughout.
+    ///
+    /// This is useful when a daemon learns the configured location of its PID file after it
+    /// has already locked a default location. An existing PID file at `new_path` is treated
+    /// as it would be by [`PidFile::new`]: stale and invalid PID files are replaced, while
+    /// PID files which are still held produce an error. The PID file is then renamed into
+    /// place, or, when `new_path` is on another filesystem, written at `new_path` before the
+    /// old PID file is removed. Either way, when this returns, the PID file is removed from
+    /// `new_path` when this [`PidFile`] is dropped.
+    ///
+    /// PID files created with [`PidFile::new_at`], [`PidFile::from_fd`] or
+    /// [`Builder::lock_dir`] can't be moved, and return an error with
+    /// [`io::ErrorKind::Unsupported`]. PID files locked with [`Builder::fcntl_lock`] can't
+    /// be moved to another filesystem.
+    pub fn rename(&mut self, new_path: impl Into<PathBuf>) -> Result<(), io::Error> {
+        let new_path = new_path.into();
+        let _span = crate::diag::debug_span!("pidfile", path = %new_path.display()).entered();
+
+        #[cfg(unix)]
+        let relative = self.dir.is_some() || self.passed_fd;
+        #[cfg(not(unix))]
+        let relative = false;
+
+        self.check_exclusive("moved")?;
+        if relative || self.lock_dir.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only PID files created at a path can be moved",
+            ));
+        }
+
+        let registration = Registration::new(&new_path)?;
+        loop {
+            let read = std::fs::read(&new_path);
+            let (found, owner) = builder::found_owner(&new_path, read, &CheckPolicy::default());
+            // As in `PidFile::new`, a stale PID file is only replaced under the lock.
+            reclaim(&new_path, owner)?;
+
+            let lock = SidecarLock::acquire(&new_path, Instant::now() + LOCK_WAIT)?;
+            let moved = lock.claim(
+                &found,
+                || builder::read_existing(&new_path),
+                || self.move_file(&new_path),
+            )?;
+            if moved.is_some() {
+                break;
+            }
+            crate::diag::trace!(path=%new_path.display(), "PID file {path} was replaced by another process while it was checked, checking it again", path = new_path.display());
+        }
+        crate::diag::trace!(path=%new_path.display(), "Moved PID file from {old} to {path}", old = self.path.display(), path = new_path.display());
+
+        #[cfg(all(unix, feature = "signal-cleanup"))]
+        {
+            self.published = crate::cleanup::publish(registration.key());
+        }
+        registration.record_file_id();
+        self.path = new_path;
+        self._registration = Some(registration);
+        Ok(())
+    }
+
+    /// Move the PID file over whatever is at `new_path`, for [`PidFile::rename`].
+    fn move_file(&self, new_path: &Path) -> Result<(), io::Error> {
+        match std::fs::rename(&self.path, new_path) {
+            Err(error) if error.raw_os_error() == Some(libc::EXDEV) => {
+                // A copy of the PID file wouldn't carry over its `fcntl` lock.
+                #[cfg(unix)]
+                if self.lock_file.is_some() {
+                    return Err(error);
+                }
+
+                let contents = self.read_own_contents()?.render();
+                let mut tmp = std::ffi::OsString::from(".");
+                tmp.push(new_path.file_name().unwrap_or_default());
+                tmp.push(format!(".{pid}.tmp", pid = self.pid));
+                crate::mode::replace(new_path, &new_path.with_file_name(tmp), &contents)?;
+                std::fs::remove_file(&self.path)
+            }
+            result => result,
+        }
+    }
+
+    /// Create a [`Builder`] to configure a new PID file at the given path.
+    pub fn builder(path: impl Into<PathBuf>) -> Builder {
+        Builder::new(path)
+    }
+
+    /// Check if a PID file is in use at this path.
+    ///
+    /// A PID file which is empty, contains only whitespace, or doesn't contain a valid PID
+    /// (including a number out of range for a PID) is reported as unlocked, and is left
+    /// alone, since this never modifies the PID file. [`PidFile::new`] replaces such a PID
+    /// file. If this function returns an error, the PID file could not be accessed, or its
+    /// owner could not be checked.
+    pub fn is_locked(path: &Path) -> Result<bool, io::Error> {
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+        owner_locked(path, pid_file_owner(path, &CheckPolicy::default()))
+    }
+
+    /// How much longer the stale PID file at this path must settle before it can be reclaimed.
+    ///
+    /// When a [`Builder::grace_period`] is configured, a PID file whose owner is no longer
+    /// running is only reclaimed once it has gone unmodified for the whole grace period.
+    /// This returns the time remaining until such a PID file becomes reclaimable, so that
+    /// callers can schedule their own retries rather than blocking.
+    ///
+    /// Returns `None` when there is no PID file, when it can be reclaimed immediately, or
+    /// when its owner is still running.
+    pub fn settle_remaining(path: &Path, grace: Duration) -> Result<Option<Duration>, io::Error> {
+        let policy = CheckPolicy {
+            grace: Some(grace),
+            ..Default::default()
+        };
+
+        match pid_file_owner(path, &policy)? {
+            Owner::Settling(_, remaining) => Ok(Some(remaining)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if a PID file is in use at this path, giving up after `timeout`.
+    ///
+    /// This behaves like [`PidFile::is_locked`], but performs the check on a worker thread,
+    /// so that callers with their own deadlines aren't stalled by a slow filesystem or an
+    /// overloaded system. If the check doesn't complete within `timeout`, this returns an
+    /// error with [`io::ErrorKind::TimedOut`], and the check may continue in the background.
+    pub fn is_locked_timeout(path: &Path, timeout: Duration) -> Result<bool, io::Error> {
+        let path = path.to_path_buf();
+        timeout::with_timeout(timeout, move || PidFile::is_locked(&path))
+    }
+
+    /// Update the heartbeat timestamp stored in this PID file.
+    ///
+    /// PID files don't carry a heartbeat unless this is called. Once it has been called,
+    /// watchdogs can use [`PidFile::last_heartbeat`] to detect a process which is still
+    /// running, but which has stopped updating its heartbeat (e.g. because it is hung).
+    ///
+    /// The file is rewritten atomically, so readers never observe partial contents.
+    /// Returns an error if the PID file on disk no longer belongs to this process, and an
+    /// error with [`io::ErrorKind::Unsupported`] for [`PidFile::new_shared`] PID files.
+    pub fn heartbeat(&self) -> Result<(), io::Error> {
+        self.update_contents(|contents| {
+            contents.set_field("heartbeat", to_timestamp(SystemTime::now()));
+        })
+    }
+
+    /// Update the modification time of this PID file to now, without rewriting it.
+    ///
+    /// This is lighter than [`PidFile::heartbeat`] when only the modification time matters,
+    /// e.g. to keep `/tmp` cleaners from removing the PID file, or for watchdogs which check
+    /// [`PidFile::modified`]. Since the contents are untouched, readers can never observe a
+    /// partial write. Unlike [`PidFile::heartbeat`], this doesn't check that the PID file on
+    /// disk still belongs to this process. Returns an error if the PID file is missing, and an
+    /// error with [`io::ErrorKind::Unsupported`] for [`PidFile::new_shared`] PID files.
+    #[cfg(unix)]
+    pub fn touch(&self) -> Result<(), io::Error> {
+        self.check_exclusive("touched")?;
+        match (&self.dir, &self.lock_file) {
+            (Some(dir), _) => at::touch_at(Some(dir.as_fd()), &self.path),
+            (None, Some(file)) => at::touch_file(file),
+            (None, None) => at::touch_at(None, &self.path),
+        }
+    }
+
+    /// Control whether the PID file is removed when it is dropped during a panic.
+    ///
+    /// By default, the PID file is always removed when it is dropped, including while
+    /// unwinding from a panic. Setting this to `false` leaves the PID file in place when
+    /// it is dropped while the thread is panicking, so that a crashing process leaves
+    /// evidence behind for post-mortem inspection.
+    pub fn with_cleanup_on_panic(mut self, cleanup_on_panic: bool) -> Self {
+        self.cleanup_on_panic = cleanup_on_panic;
+        self
+    }
+
+    /// Renew the lease on this PID file, so that it expires `lease` from now.
+    ///
+    /// PID files created with [`Builder::lease`] record an expiry time, after which they
+    /// are treated as free even if the owning process is still running. The owner must
+    /// renew its lease before then to keep holding the PID file. Renewing a PID file which
+    /// was created without a lease starts one.
+    ///
+    /// The file is rewritten atomically, so readers never observe partial contents.
+    /// Returns an error if the PID file on disk no longer belongs to this process.
+    pub fn renew(&self, lease: Duration) -> Result<(), io::Error> {
+        self.update_contents(|contents| {
+            contents.set_field("expires", to_timestamp(SystemTime::now() + lease));
+        })
+    }
+
+    /// Set `key=value` metadata fields in this PID file, without changing the PID.
+    ///
+    /// This is useful for publishing runtime status (such as a socket path or a version)
+    /// alongside the PID. Fields which already exist are replaced in place, and new fields
+    /// are added after the existing ones. The PID always stays on the first line, so
+    /// readers which only parse the PID are unaffected. Use [`PidFile::read_field`] to read
+    /// the fields back.
+    ///
+    /// The file is rewritten atomically, so readers always observe a consistent snapshot.
+    /// Returns an error with [`io::ErrorKind::InvalidInput`] if a key is empty or contains
+    /// `=`, or if a key or value contains a line break, and an error if the PID file on disk
+    /// no longer belongs to this process.
+    pub fn renew_contents<K, V>(
+        &self,
+        fields: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), io::Error>
+    where
+        K: AsRef<str>,
+        V: std::fmt::Display,
+    {
+        let fields = fields
+            .into_iter()
+            .map(|(key, value)| {
+                let key = key.as_ref().trim().to_owned();
+                let value = value.to_string();
+                if key.is_empty() || key.contains(['=', '\n', '\r']) || value.contains(['\n', '\r'])
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid PID file field {key:?}"),
+                    ));
+                }
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>, io::Error>>()?;
+
+        self.update_contents(|contents| {
+            for (key, value) in fields {
+                contents.set_field(&key, value);
+            }
+        })
+    }
+
+    /// Add a `key=value` annotation to this PID file, such as a version or a config hash.
+    ///
+    /// This is [`PidFile::renew_contents`] for a single field: an existing annotation with
+    /// the same key is replaced, and the PID file is rewritten atomically, so concurrent
+    /// readers never observe a partial write. Use [`PidFile::read_fields`] to read all the
+    /// annotations back.
+    pub fn write_extra_line(
+        &self,
+        key: &str,
+        value: impl std::fmt::Display,
+    ) -> Result<(), io::Error> {
+        self.renew_contents([(key, value)])
+    }
+
+    /// Read all the `key=value` metadata fields from the PID file at this path.
+    ///
+    /// This includes annotations written with [`PidFile::write_extra_line`], as well as the
+    /// metadata recorded by this crate, such as `heartbeat`. Lines after the PID which
+    /// aren't `key=value` pairs are skipped.
+    pub fn read_fields(path: &Path) -> Result<BTreeMap<String, String>, io::Error> {
+        let info = std::fs::read_to_string(path)?;
+        let contents = Contents::parse(path, &info)?;
+        Ok(contents
+            .fields()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect())
+    }
+
+    /// Read a `key=value` metadata field from the PID file at this path.
+    ///
+    /// Returns `Ok(None)` if the PID file exists, but doesn't have the field.
+    pub fn read_field(path: &Path, key: &str) -> Result<Option<String>, io::Error> {
+        let info = std::fs::read_to_string(path)?;
+        let contents = Contents::parse(path, &info)?;
+        Ok(contents.field(key).map(str::to_owned))
+    }
+
+    /// Read the last heartbeat timestamp from the PID file at this path.
+    ///
+    /// Returns `Ok(None)` if the PID file exists, but has never had a heartbeat recorded.
+    pub fn last_heartbeat(path: &Path) -> Result<Option<SystemTime>, io::Error> {
+        let info = std::fs::read_to_string(path)?;
+        let contents = Contents::parse(path, &info)?;
+        contents.field("heartbeat").map(from_timestamp).transpose()
+    }
+
+    /// Read the parent PID recorded in the PID file at this path.
+    ///
+    /// The parent PID is only recorded when the PID file was created with
+    /// [`Builder::record_ppid`]. A monitoring tool can compare it against the current
+    /// parent of the owning process to detect orphaned workers, e.g. those whose parent
+    /// has exited and which have been reparented to init.
+    ///
+    /// Returns `Ok(None)` if the PID file exists, but has no parent PID recorded.
+    pub fn parent_pid(path: &Path) -> Result<Option<libc::pid_t>, io::Error> {
+        let info = std::fs::read_to_string(path)?;
+        let contents = Contents::parse(path, &info)?;
+        contents
+            .field("ppid")
+            .map(|ppid| {
+                ppid.parse()
+                    .map_err(|error| PidFileError::InvalidPid(error).into())
+            })
+            .transpose()
+    }
+
+    /// How long this PID file has been held.
+    ///
+    /// This uses the start time recorded with [`Builder::record_start_time`] when present,
+    /// and otherwise falls back to the modification time of the PID file. A start time in
+    /// the future (e.g. due to clock skew) is treated as an age of zero.
+    pub fn age(&self) -> Result<Duration, io::Error> {
+        let contents = self.read_own_contents()?;
+        let started = match contents.field("started") {
+            Some(started) => from_timestamp(started)?,
+            None => self.modified()?,
+        };
+
+        Ok(SystemTime::now()
+            .duration_since(started)
+            .unwrap_or(Duration::ZERO))
+    }
+
+    /// The current contents of this PID file, read from disk.
+    ///
+    /// This reflects what is on disk now, including any metadata, so it can be compared
+    /// against what this process wrote to detect external tampering. Returns an error if
+    /// the PID file has been removed.
+    pub fn contents(&self) -> Result<String, io::Error> {
+        #[cfg(unix)]
+        match (&self.dir, &self.lock_file) {
+            (Some(dir), _) => at::read_at(dir.as_fd(), &self.path),
+            (None, Some(file)) => fcntl::read(file),
+            (None, None) => std::fs::read_to_string(&self.path),
+        }
+
+        #[cfg(not(unix))]
+        std::fs::read_to_string(&self.path)
+    }
+
+    /// The modification time of this PID file.
+    ///
+    /// This is updated when the PID file is created or rewritten, and by [`PidFile::touch`].
+    pub fn modified(&self) -> Result<SystemTime, io::Error> {
+        #[cfg(unix)]
+        if let Some(dir) = &self.dir {
+            return at::modified_at(dir.as_fd(), &self.path);
+        }
+
+        std::fs::metadata(&self.path)?.modified()
+    }
+
+    /// Atomically rewrite this PID file, after applying `update` to its current contents.
+    fn update_contents(&self, update: impl FnOnce(&mut Contents)) -> Result<(), io::Error> {
+        self.check_exclusive("rewritten")?;
+        let mut contents = self.read_own_contents()?;
+        update(&mut contents);
+        self.replace_contents(&contents.render())
+    }
+
+    /// Read the contents of this PID file from disk, checking that they still belong to us.
+    fn read_own_contents(&self) -> Result<Contents, io::Error> {
+        let info = self.contents()?;
+        let contents = Contents::parse(&self.path, &info)?;
+        let listed = self.shared
+            && shared::holders(&contents).is_some_and(|holders| holders.contains(&self.pid));
+        if contents.pid() != self.pid && !listed {
+            self.lock_lost(contents.pid());
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "PID File {path} is no longer held by this process",
+                    path = self.path.display()
+                ),
+            ));
+        }
+        Ok(contents)
+    }
+
+    /// Atomically replace the contents of this PID file, by writing a temporary file and renaming it.
+    pub(crate) fn replace_contents(&self, contents: &str) -> Result<(), io::Error> {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(self.path.file_name().unwrap_or_default());
+        name.push(format!(".{}.tmp", self.pid));
+        let tmp = self.path.with_file_name(name);
+
+        #[cfg(unix)]
+        if let Some(dir) = &self.dir {
+            return at::replace_at(dir.as_fd(), &tmp, &self.path, contents);
+        }
+
+        // Renaming a new file into place would leave the lock behind on the old file.
+        #[cfg(unix)]
+        if let Some(file) = &self.lock_file {
+            return fcntl::rewrite(file, contents);
+        }
+
+        crate::mode::replace(&self.path, &tmp, contents)
+    }
+}
+
+/// Lock the PID file at `path` for this process, returning a guard which unlocks it when
+/// dropped.
+///
+/// This is the same as [`PidFile::new`], for call sites which read better as a lock:
+///
+/// ```rust
+/// # fn main() -> Result<(), std::io::Error> {
+/// # let tmp = tempfile::tempdir()?;
+/// # let path = tmp.path().join("lock.pid");
+/// let guard = pidfile::lock(&path)?;
+/// // ...
+/// pidfile::unlock(guard);
+/// # Ok(())
+/// # }
+/// ```
+pub fn lock(path: impl Into<PathBuf>) -> Result<PidFile, io::Error> {
+    PidFile::new(path)
+}
+
+/// Unlock a PID file locked with [`lock`], removing it.
+///
+/// This is the same as dropping the guard.
+pub fn unlock(guard: PidFile) {
+    drop(guard);
+}
+
+/// The environment variable which, when set, prevents PID files from being removed on drop.
+const KEEP_ENV_VAR: &str = "PIDFILE_KEEP";
+
+/// The PID file path in `value`, the value of the environment variable `var`.
+fn env_path(var: &str, value: Option<std::ffi::OsString>) -> Result<PathBuf, io::Error> {
+    match value {
+        Some(path) if !path.is_empty() => Ok(path.into()),
+        Some(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the PID file path in the environment variable {var} is empty"),
+        )),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the environment variable {var} for the PID file path is not set"),
+        )),
+    }
+}
+
+/// Whether the value of [`KEEP_ENV_VAR`] requests that PID files be kept.
+fn keep_requested(value: Option<&std::ffi::OsStr>) -> bool {
+    value.is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+impl PartialEq for PidFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for PidFile {}
+
+impl std::hash::Hash for PidFile {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+
+        if keep_requested(std::env::var_os(KEEP_ENV_VAR).as_deref()) {
+            crate::diag::debug!(path=%self.path.display(), "Keeping PID file at {path} since {KEEP_ENV_VAR} is set", path = self.path.display());
+            return;
+        }
+
+        if !self.cleanup_on_panic && std::thread::panicking() {
+            crate::diag::debug!(path=%self.path.display(), "Keeping PID file at {path} while panicking", path = self.path.display());
+            return;
+        }
+
+        // Withdraw the path from the signal handler first, so that it never removes a PID
+        // file which another process created after we removed ours.
+        #[cfg(all(unix, feature = "signal-cleanup"))]
+        drop(self.published.take());
+
+        match self.remove() {
+            Ok(_) => {}
+            Err(error) => eprintln!(
+                "Encountered an error removing the PID file at {}: {}",
+                self.path.display(),
+                error
+            ),
+        }
+
+        builder::remove_created_dirs(&self.created_dirs);
+    }
+}
+
+impl PidFile {
+    /// A [`PidFile`] for a file at `path` which has just been locked by this process.
+    pub(crate) fn locked(
+        path: PathBuf,
+        pid: libc::pid_t,
+        registration: Option<Registration>,
+    ) -> Self {
+        if let Some(registration) = &registration {
+            registration.record_file_id();
+        }
+
+        Self {
+            path,
+            pid,
+            #[cfg(unix)]
+            dir: None,
+            #[cfg(unix)]
+            lock_file: None,
+            #[cfg(unix)]
+            passed_fd: false,
+            shared: false,
+            lock_dir: None,
+            reclaimed: None,
+            written: Contents::new(pid).render(),
+            created_dirs: Vec::new(),
+            #[cfg(all(unix, feature = "signal-cleanup"))]
+            published: registration
+                .as_ref()
+                .and_then(|registration| crate::cleanup::publish(registration.key())),
+            io_timeout: None,
+            keep: false,
+            cleanup_on_panic: true,
+            on_lock_lost: None,
+            _registration: registration,
+        }
+    }
+
+    /// The path which identifies this PID file, for equality and hashing.
+    fn key(&self) -> &Path {
+        self._registration
+            .as_ref()
+            .map_or(&self.path, Registration::key)
+    }
+
+    /// Remove this PID file (or its lock directory) from disk.
+    ///
+    /// PID files passed in with [`PidFile::from_fd`] are truncated instead, and shared PID
+    /// files are only removed by their last holder.
+    fn remove(&self) -> Result<(), io::Error> {
+        if self.shared {
+            return self.leave_shared();
+        }
+
+        #[cfg(unix)]
+        if let (true, Some(file)) = (self.passed_fd, &self.lock_file) {
+            return file.set_len(0);
+        }
+
+        if let Some(lock_dir) = &self.lock_dir {
+            let lock_dir = lock_dir.clone();
+            return self.bounded(move || std::fs::remove_dir_all(lock_dir));
+        }
+
+        #[cfg(unix)]
+        if let Some(dir) = &self.dir {
+            return at::unlink_at(dir.as_fd(), &self.path);
+        }
+
+        let path = self.path.clone();
+        self.bounded(move || std::fs::remove_file(path))
+    }
+
+    /// Run a filesystem operation on this PID file, bounded by its [`Builder::io_timeout`].
+    fn bounded(
+        &self,
+        operation: impl FnOnce() -> Result<(), io::Error> + Send + 'static,
+    ) -> Result<(), io::Error> {
+        match self.io_timeout {
+            Some(timeout) => timeout::with_timeout(timeout, operation),
+            None => operation(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+    use std::cell::Cell;
+
+    thread_local! {
+        /// The PID for `current_pid` to report on this thread, set by [`with_pid`].
+        pub(super) static PID: Cell<Option<libc::pid_t>> = const { Cell::new(None) };
+    }
+
+    /// Run `operation` as if this process had the PID `pid`.
+    fn with_pid<T>(pid: libc::pid_t, operation: impl FnOnce() -> T) -> T {
+        struct Restore(Option<libc::pid_t>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                PID.with(|current| current.set(self.0));
+            }
+        }
+
+        let _restore = Restore(PID.with(|current| current.replace(Some(pid))));
+        operation()
+    }
+
+    #[test]
+    fn test_settle_remaining() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-settle.pid");
+        let grace = Duration::from_secs(3600);
+        assert_eq!(PidFile::settle_remaining(&path, grace).unwrap(), None);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let child_pid = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, format!("{child_pid}")).unwrap();
+
+        let remaining = PidFile::settle_remaining(&path, grace).unwrap().unwrap();
+        assert!(remaining <= grace);
+        assert_eq!(
+            PidFile::settle_remaining(&path, Duration::ZERO).unwrap(),
+            None
+        );
+
+        let error = silenced(|| {
+            PidFile::builder(&path)
+                .grace_period(grace)
+                .create()
+                .unwrap_err()
+        });
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(PidFile::settle_remaining(&path, grace).unwrap(), None);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_unknown_liveness_errno() {
+        let liveness = Liveness::Unknown(io::Error::from_raw_os_error(libc::EINVAL));
+        let error = silenced(|| liveness_in_use(1234, liveness).unwrap_err());
+        assert_eq!(error.raw_os_error(), Some(libc::EINVAL));
+        assert!(error
+            .to_string()
+            .contains(&format!("os error {}", libc::EINVAL)));
+    }
+
+    #[test]
+    fn test_from_env() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-env.pid");
+        let var = "PIDFILE_TEST_FROM_ENV";
+
+        // Setting environment variables would race with the other tests, which read them.
+        let error = PidFile::from_env(var).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains(var));
+
+        let error = env_path(var, Some("".into())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(error.to_string().contains(var));
+
+        assert_eq!(env_path(var, Some(path.clone().into())).unwrap(), path);
+    }
+
+    #[test]
+    fn test_pid_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-test.pid");
+        let pid_file = PidFile::new(path.clone()).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+        assert!(!PidFile::is_locked(&path).unwrap());
+    }
+
+    #[test]
+    fn test_pid_file_held_in_process() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-held.pid");
+        let pid_file = PidFile::new(path.clone()).unwrap();
+
+        let error = silenced(|| PidFile::new(path.clone()).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        drop(pid_file);
+        let pid_file = PidFile::new(path.clone()).unwrap();
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_in_use_message() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-message.pid");
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        std::fs::write(&path, format!("{pid}")).unwrap();
+
+        let error = silenced(|| PidFile::new(&path).unwrap_err());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(
+            error.to_string(),
+            format!("PID File {} is held by PID {pid}", path.display())
+        );
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<PidFileError>(),
+            Some(&PidFileError::InUse {
+                path: path.display().to_string(),
+                pid: pid as libc::pid_t
+            })
+        );
+    }
+
+    #[test]
+    fn test_keep_requested() {
+        use std::ffi::OsStr;
+
+        assert!(!keep_requested(None));
+        assert!(!keep_requested(Some(OsStr::new(""))));
+        assert!(!keep_requested(Some(OsStr::new("0"))));
+        assert!(keep_requested(Some(OsStr::new("1"))));
+        assert!(keep_requested(Some(OsStr::new("yes"))));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-heartbeat.pid");
+        let pid_file = PidFile::new(path.clone()).unwrap();
+        assert_eq!(PidFile::last_heartbeat(&path).unwrap(), None);
+
+        pid_file.heartbeat().unwrap();
+        let heartbeat = PidFile::last_heartbeat(&path).unwrap().unwrap();
+        assert!(heartbeat.elapsed().unwrap() < std::time::Duration::from_secs(60));
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_ignores_planted_tmp() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("planted.pid");
+        let target = tmp.path().join("target");
+        std::fs::write(&target, "precious").unwrap();
+
+        let pid_file = PidFile::new(&path).unwrap();
+        let planted = tmp
+            .path()
+            .join(format!(".planted.pid.{}.tmp", pid_file.pid));
+        std::os::unix::fs::symlink(&target, &planted).unwrap();
+
+        pid_file.heartbeat().unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "precious");
+        assert!(!planted.exists());
+        assert!(PidFile::last_heartbeat(&path).unwrap().is_some());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let path = Path::new("reclaim.pid");
+        silenced(|| {
+            assert_eq!(reclaim(path, Ok(Owner::Dead(1234))).unwrap(), Some(1234));
+            assert_eq!(reclaim(path, Ok(Owner::Empty)).unwrap(), None);
+            assert_eq!(reclaim(path, Ok(Owner::Absent)).unwrap(), None);
+
+            let error = reclaim(path, Ok(Owner::Alive(1234))).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        });
+    }
+
+    #[test]
+    fn test_unusable_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("unusable.pid");
+        let over_range = (libc::pid_t::MAX as i64 + 1).to_string();
+
+        for contents in ["garbage", "", " \n\t\n", over_range.as_str()] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(is_unusable(&pid_file_owner(&path, &CheckPolicy::default())));
+
+            // Checking leaves the PID file alone, while creating replaces it.
+            assert!(
+                !silenced(|| PidFile::is_locked(&path)).unwrap(),
+                "{contents:?}"
+            );
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+            let pid_file = silenced(|| PidFile::new(&path)).unwrap();
+            assert_eq!(pid_file.reclaimed_pid(), None);
+            assert!(PidFile::is_locked(&path).unwrap());
+            drop(pid_file);
+        }
+    }
+
+    #[test]
+    fn test_reclaimed_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("reclaimed.pid");
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(pid_file.reclaimed_pid(), None);
+        drop(pid_file);
+
+        std::fs::write(&path, "garbage").unwrap();
+        let pid_file = silenced(|| PidFile::new(&path).unwrap());
+        assert_eq!(pid_file.reclaimed_pid(), None);
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id() as libc::pid_t;
+        child.wait().unwrap();
+        std::fs::write(&path, dead.to_string()).unwrap();
+        let pid_file = silenced(|| PidFile::new(&path).unwrap());
+        assert_eq!(pid_file.reclaimed_pid(), Some(dead));
+        drop(pid_file);
+
+        #[cfg(unix)]
+        {
+            std::fs::write(&path, dead.to_string()).unwrap();
+            let pid_file = silenced(|| Builder::new(&path).fcntl_lock(true).create().unwrap());
+            assert_eq!(pid_file.reclaimed_pid(), Some(dead));
+        }
+    }
+
+    #[test]
+    fn test_rename() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-rename.pid");
+        let new_path = tmp.path().join("pidfile-renamed.pid");
+
+        let mut pid_file = PidFile::new(&path).unwrap();
+        pid_file.rename(&new_path).unwrap();
+        assert_eq!(pid_file.path(), new_path);
+        assert!(!path.exists());
+        assert!(PidFile::is_locked(&new_path).unwrap());
+
+        // The old path is no longer held, so it can be locked again.
+        let other = PidFile::new(&path).unwrap();
+        let error = silenced(|| pid_file.rename(&path).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(other);
+
+        drop(pid_file);
+        assert!(!new_path.exists());
+    }
+
+    #[test]
+    fn test_into_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-into-path.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(pid_file.into_path(), path);
+        assert!(path.exists());
+
+        // The kept file still contains our PID, so it must be removed before it is reused.
+        std::fs::remove_file(&path).unwrap();
+        let pid_file = PidFile::new(&path).unwrap();
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_cleanup_on_panic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-panic.pid");
+
+        let pid_file = PidFile::new(&path).unwrap().with_cleanup_on_panic(false);
+        let result = std::thread::spawn(move || {
+            let _pid_file = pid_file;
+            panic!("leaving the PID file behind");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+
+        let pid_file = PidFile::new(&path).unwrap().with_cleanup_on_panic(false);
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_age() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-age.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+        assert!(pid_file.age().unwrap() < Duration::from_secs(60));
+        drop(pid_file);
+
+        let pid_file = PidFile::builder(&path)
+            .record_start_time(true)
+            .create()
+            .unwrap();
+        let mut contents = pid_file.read_own_contents().unwrap();
+        let started = to_timestamp(SystemTime::now() - Duration::from_secs(3600));
+        contents.set_field("started", started);
+        pid_file.replace_contents(&contents.render()).unwrap();
+        assert!(pid_file.age().unwrap() >= Duration::from_secs(3600));
+
+        contents.set_field("started", started + 7200);
+        pid_file.replace_contents(&contents.render()).unwrap();
+        assert_eq!(pid_file.age().unwrap(), Duration::ZERO);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_contains_own_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-own.pid");
+
+        // Another running process stands in for this one, so the PID is not our own.
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as libc::pid_t;
+        std::fs::write(&path, format!("{pid}")).unwrap();
+
+        with_pid(pid, || {
+            assert_eq!(current_pid().unwrap(), pid);
+            assert!(PidFile::is_locked(&path).unwrap());
+            let error = silenced(|| PidFile::new(&path).unwrap_err());
+            assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+            assert_eq!(
+                error.get_ref().unwrap().downcast_ref::<PidFileError>(),
+                Some(&PidFileError::HeldByThisProcess {
+                    path: path.display().to_string()
+                })
+            );
+        });
+        assert_ne!(current_pid().unwrap(), pid);
+        assert!(path.exists());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_lease() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-lease.pid");
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let mut contents = Contents::new(child.id() as libc::pid_t);
+        contents.set_field("expires", to_timestamp(SystemTime::now()) + 3600);
+        std::fs::write(&path, contents.render()).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        contents.set_field("expires", to_timestamp(SystemTime::now()) - 1);
+        std::fs::write(&path, contents.render()).unwrap();
+        assert!(!PidFile::is_locked(&path).unwrap());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let pid_file = PidFile::builder(&path)
+            .lease(Duration::from_secs(3600))
+            .create()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        pid_file.renew(Duration::ZERO).unwrap();
+        assert!(!PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_boot_time() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-boot.pid");
+        let boot = crate::boot::boot_time().unwrap();
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let mut contents = Contents::new(child.id() as libc::pid_t);
+        contents.set_field("boot", boot - 3600);
+        std::fs::write(&path, contents.render()).unwrap();
+        assert!(!PidFile::is_locked(&path).unwrap());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let pid_file = PidFile::builder(&path)
+            .record_boot_time(true)
+            .create()
+            .unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        assert_eq!(
+            PidFile::read_field(&path, "boot").unwrap(),
+            Some(boot.to_string())
+        );
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_parent_pid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-ppid.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(PidFile::parent_pid(&path).unwrap(), None);
+        drop(pid_file);
+
+        let pid_file = PidFile::builder(&path).record_ppid(true).create().unwrap();
+        assert_eq!(PidFile::parent_pid(&path).unwrap(), Some(parent_pid()));
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_renew_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-renew-contents.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+
+        pid_file
+            .renew_contents([("socket", "/tmp/app.sock"), ("version", "1")])
+            .unwrap();
+        pid_file.renew_contents([("version", 2)]).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\nsocket=/tmp/app.sock\nversion=2\n", std::process::id())
+        );
+        assert_eq!(
+            PidFile::read_field(&path, "version").unwrap().as_deref(),
+            Some("2")
+        );
+        assert_eq!(PidFile::read_field(&path, "missing").unwrap(), None);
+
+        let error = pid_file.renew_contents([("a=b", "c")]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        let error = pid_file
+            .renew_contents([("key", "two\nlines")])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_lock_unlock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-lock.pid");
+
+        let guard = lock(&path).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        unlock(guard);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_eq_hash() {
+        use std::collections::HashSet;
+        use std::hash::BuildHasher;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-eq.pid");
+        let other = PidFile::new(tmp.path().join("pidfile-other.pid")).unwrap();
+
+        let mut held = HashSet::new();
+        held.insert(PidFile::new(&path).unwrap());
+        assert!(held.insert(other));
+        assert_eq!(held.len(), 2);
+
+        let state = std::collections::hash_map::RandomState::new();
+        let first = held.iter().find(|p| p.path() == path).unwrap();
+        let hash = state.hash_one(first);
+        held.clear();
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(state.hash_one(&pid_file), hash);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_touch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-touch.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        let time = libc::timespec {
+            tv_sec: to_timestamp(old) as libc::time_t,
+            tv_nsec: 0,
+        };
+        let name = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        // SAFETY: `name` is a valid C string, and the times are two valid timespecs.
+        #[allow(unsafe_code)]
+        let result =
+            unsafe { libc::utimensat(libc::AT_FDCWD, name.as_ptr(), [time; 2].as_ptr(), 0) };
+        assert_eq!(result, 0);
+        assert!(pid_file.modified().unwrap() <= old);
+
+        pid_file.touch().unwrap();
+        assert!(pid_file.modified().unwrap() > old);
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            pid_file.touch().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_current_pid_after_fork() {
+        let parent = current_pid().unwrap();
+        assert_eq!(parent, std::process::id() as libc::pid_t);
+
+        // SAFETY: The child only makes system calls before exiting.
+        #[allow(unsafe_code)]
+        unsafe {
+            let pid = libc::fork();
+            assert!(pid >= 0, "fork failed");
+            if pid == 0 {
+                let child = current_pid();
+                libc::_exit(match child {
+                    Ok(child) if child != parent && child == libc::getpid() => 0,
+                    _ => 1,
+                });
+            }
+
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+        }
+    }
+
+    #[test]
+    fn test_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-contents.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(pid_file.contents().unwrap(), std::process::id().to_string());
+
+        std::fs::write(&path, "1\n").unwrap();
+        assert_eq!(pid_file.contents().unwrap(), "1\n");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            pid_file.contents().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn test_extra_lines() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-extra.pid");
+        let pid_file = PidFile::new(&path).unwrap();
+        assert!(PidFile::read_fields(&path).unwrap().is_empty());
+
+        pid_file.write_extra_line("version", "1.2.3").unwrap();
+        pid_file.write_extra_line("config", "abc123").unwrap();
+        pid_file.write_extra_line("version", "1.2.4").unwrap();
+        assert_eq!(
+            PidFile::read_fields(&path).unwrap(),
+            BTreeMap::from([
+                ("config".to_owned(), "abc123".to_owned()),
+                ("version".to_owned(), "1.2.4".to_owned()),
+            ])
+        );
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        let error = pid_file.write_extra_line("bad\nkey", 1).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-empty.pid");
+        std::fs::write(&path, "").unwrap();
+        assert!(!PidFile::is_locked(&path).unwrap());
+        assert_eq!(
+            pid_file_owner(&path, &CheckPolicy::default()).unwrap(),
+            Owner::Empty
+        );
+
+        let pid_file = PidFile::new(&path).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let path = Path::new("/tmp/pidfile-test.pid");
+        std::fs::write(path, "not a pid").unwrap();
+        silenced(|| {
+            assert!(
+                !PidFile::is_locked(path).unwrap(),
+                "Invalid file should not be locked."
+            )
+        });
+        assert!(
+            path.exists(),
+            "Invalid file should exist after checking for locks."
+        );
+
+        let pid_file = silenced(|| PidFile::new(path).unwrap());
+        assert!(
+            PidFile::is_locked(path).unwrap(),
+            "PID file should be locked after creation."
+        );
+        drop(pid_file);
+        assert!(
+            !PidFile::is_locked(path).unwrap(),
+            "PID file should not be locked after drop."
+        );
+    }
+}
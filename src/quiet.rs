@@ -0,0 +1,83 @@
+//! Quieting the diagnostics for PID files which are in use while retrying acquisition.
+//!
+//! Waiting for a PID file retries acquisition many times, and logging that the PID file is
+//! in use on every attempt would flood the logs. Only the first attempt logs at the usual
+//! level, and later attempts log at trace level, until the waiter succeeds or gives up.
+
+use std::cell::Cell;
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restores the previous value of [`QUIET`] when dropped, even if the operation panics.
+struct Restore(bool);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        QUIET.with(|quiet| quiet.set(self.0));
+    }
+}
+
+/// Run `operation` with in-use diagnostics downgraded to trace level.
+pub(crate) fn quietly<T>(operation: impl FnOnce() -> T) -> T {
+    let _restore = Restore(QUIET.with(|quiet| quiet.replace(true)));
+    operation()
+}
+
+/// Run `operation` without a `tracing` subscriber, for tests which expect it to log errors.
+#[cfg(test)]
+pub(crate) fn silenced<T>(operation: impl FnOnce() -> T) -> T {
+    tracing::subscriber::with_default(tracing::subscriber::NoSubscriber::new(), operation)
+}
+
+/// Whether in-use diagnostics are currently downgraded to trace level.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+pub(crate) fn is_quiet() -> bool {
+    QUIET.with(Cell::get)
+}
+
+/// Log that a PID file is in use, at `$level`, or at trace level while retrying.
+#[cfg(feature = "tracing")]
+macro_rules! in_use {
+    ($level:ident, $($arg:tt)+) => {
+        if $crate::quiet::is_quiet() {
+            $crate::diag::trace!($($arg)+)
+        } else {
+            $crate::diag::$level!($($arg)+)
+        }
+    };
+}
+
+/// Without `tracing`, every level compiles to the same nothing.
+#[cfg(not(feature = "tracing"))]
+macro_rules! in_use {
+    ($level:ident, $($arg:tt)+) => {
+        $crate::diag::$level!($($arg)+)
+    };
+}
+
+pub(crate) use in_use;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quietly() {
+        assert!(!is_quiet());
+        quietly(|| {
+            assert!(is_quiet());
+            quietly(|| assert!(is_quiet()));
+            assert!(is_quiet());
+        });
+        assert!(!is_quiet());
+    }
+
+    #[test]
+    fn test_quietly_panic() {
+        let result = std::panic::catch_unwind(|| quietly(|| panic!("retrying failed")));
+        assert!(result.is_err());
+        assert!(!is_quiet());
+    }
+}
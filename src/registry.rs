@@ -0,0 +1,158 @@
+//! A process-global registry of the PID files held by this process.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use crate::quiet::in_use;
+use crate::PidFileError;
+
+/// The device and inode of a file, which identify it regardless of the path used to reach it.
+type FileId = (u64, u64);
+
+/// The paths of all PID files currently held by this process, with their file IDs once known.
+fn registry() -> &'static Mutex<HashMap<PathBuf, Option<FileId>>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, Option<FileId>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// The file ID of the file at `path`, following symlinks, if it exists.
+#[cfg(unix)]
+fn file_id(path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// File IDs are unavailable on this platform, so only paths are compared.
+#[cfg(not(unix))]
+fn file_id(_path: &Path) -> Option<FileId> {
+    None
+}
+
+/// The key for `path` in the registry.
+///
+/// This is absolute, and resolves symlinks in the parent directory where it exists. The
+/// PID file itself usually doesn't exist yet, so it can't be canonicalized.
+pub(crate) fn registry_key(path: &Path) -> Result<PathBuf, io::Error> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+
+    let canonical = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent.canonicalize().ok().map(|parent| parent.join(name)),
+        _ => None,
+    };
+    Ok(canonical.unwrap_or(path))
+}
+
+/// Forget every PID file held by this process, so that new [`PidFile`](crate::PidFile)s can be
+/// created at their paths.
+///
+/// This is only for tests which intentionally exercise edge cases, so that they can reset
+/// the registry between cases, and must never be used in production. The PID files which
+/// were held are not removed, and dropping them afterwards may release paths held by new
+/// [`PidFile`](crate::PidFile)s at the same paths.
+#[cfg(any(test, feature = "test-util"))]
+pub fn clear_registry() {
+    registry()
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .clear();
+}
+
+/// A path which is registered as held by this process.
+///
+/// The path is removed from the registry when this is dropped.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    key: PathBuf,
+}
+
+impl Registration {
+    /// Register a path as held by this process.
+    ///
+    /// Returns an error with [`io::ErrorKind::AddrInUse`] if the path is already held
+    /// by another [`PidFile`](crate::PidFile) in this process. On Unix, paths which reach
+    /// the same file as a held PID file through a symlink or hard link are also treated as
+    /// held, by comparing the device and inode of the file.
+    pub(crate) fn new(path: &Path) -> Result<Self, io::Error> {
+        let key = registry_key(path)?;
+        let id = file_id(&key);
+
+        let mut registry = registry().lock().unwrap_or_else(|error| error.into_inner());
+        let aliased = id.is_some() && registry.values().any(|held| *held == id);
+        if aliased || registry.contains_key(&key) {
+            in_use!(error, path=%path.display(), "PID File {path} is already held by this process", path = path.display());
+            return Err(PidFileError::HeldByThisProcess {
+                path: path.display().to_string(),
+            }
+            .into());
+        }
+
+        registry.insert(key.clone(), None);
+        Ok(Self { key })
+    }
+
+    /// Record the device and inode of the PID file, once it has been created.
+    pub(crate) fn record_file_id(&self) {
+        let id = file_id(&self.key);
+        let mut registry = registry().lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(held) = registry.get_mut(&self.key) {
+            *held = id;
+        }
+    }
+
+    /// Whether the registered path still reaches the file whose ID was recorded with
+    /// [`Registration::record_file_id`], or `None` if no file ID is known.
+    pub(crate) fn is_linked(&self) -> Option<bool> {
+        let registry = registry().lock().unwrap_or_else(|error| error.into_inner());
+        let recorded = (*registry.get(&self.key)?)?;
+        Some(file_id(&self.key) == Some(recorded))
+    }
+
+    /// The absolute path which is registered, with symlinks in its parent directory resolved.
+    pub(crate) fn key(&self) -> &Path {
+        &self.key
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let mut registry = registry().lock().unwrap_or_else(|error| error.into_inner());
+        registry.remove(&self.key);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::quiet::silenced;
+    use crate::PidFile;
+
+    #[test]
+    fn test_aliased_paths() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("registry.pid");
+        let symlink = tmp.path().join("registry-symlink.pid");
+        let hardlink = tmp.path().join("registry-hardlink.pid");
+        let dir = tmp.path().join("dir-symlink");
+
+        let pid_file = PidFile::new(&path).unwrap();
+        std::os::unix::fs::symlink(&path, &symlink).unwrap();
+        std::fs::hard_link(&path, &hardlink).unwrap();
+        std::os::unix::fs::symlink(tmp.path(), &dir).unwrap();
+
+        let aliases = [symlink, hardlink, dir.join("registry.pid")];
+        silenced(|| {
+            for alias in &aliases {
+                let error = PidFile::new(alias).unwrap_err();
+                assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse, "{alias:?}");
+            }
+        });
+        drop(pid_file);
+    }
+}
@@ -0,0 +1,232 @@
+//! Renewing the lease on a PID file from a background thread.
+
+use std::io;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::PidFile;
+
+/// A [`PidFile`] whose lease is renewed by a background thread, created with
+/// [`PidFile::renew_in_background`].
+///
+/// The PID file can be used through this guard as usual. Dropping the guard stops the
+/// renewal thread, waits for it to exit, and then drops the PID file, so the thread never
+/// outlives the PID file.
+#[derive(Debug)]
+pub struct LeaseRenewal {
+    pidfile: Arc<PidFile>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PidFile {
+    /// Renew the lease on this PID file every `interval` from a background thread, so that it
+    /// is held for as long as the returned guard.
+    ///
+    /// Each renewal extends the lease to `lease` from the time of the renewal, as with
+    /// [`PidFile::renew`], so `interval` should be a fraction of `lease` which leaves time
+    /// for a slow renewal to complete before the lease expires, e.g. a third of it. This
+    /// returns an error with [`io::ErrorKind::InvalidInput`] if `interval` isn't shorter
+    /// than `lease`, or is zero.
+    ///
+    /// When a renewal fails, `on_error` is called with the error on the renewal thread. A
+    /// failure with [`io::ErrorKind::AddrInUse`] means that the PID file now belongs to
+    /// another process, so the lock has been lost, and renewals stop. The loss is also
+    /// reported to the callback set with [`Builder::on_lock_lost`](crate::Builder::on_lock_lost),
+    /// before `on_error` is called. After other errors, renewals carry on at the next interval.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use pidfile::PidFile;
+    ///
+    /// # fn main() -> Result<(), std::io::Error> {
+    /// let lease = Duration::from_secs(30);
+    /// let pidfile = PidFile::builder("/tmp/myapp.pid").lease(lease).create()?;
+    /// let pidfile = pidfile.renew_in_background(lease, lease / 3, |error| {
+    ///     eprintln!("Unable to renew the lease on the PID file: {error}");
+    /// })?;
+    /// # drop(pidfile);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn renew_in_background(
+        self,
+        lease: Duration,
+        interval: Duration,
+        mut on_error: impl FnMut(io::Error) + Send + 'static,
+    ) -> Result<LeaseRenewal, io::Error> {
+        if interval.is_zero() || interval >= lease {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the renewal interval {interval:?} must be shorter than the lease {lease:?}"
+                ),
+            ));
+        }
+
+        let pidfile = Arc::new(self);
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let pidfile = Arc::clone(&pidfile);
+            let stop = Arc::clone(&stop);
+            std::thread::Builder::new()
+                .name("pidfile-lease".into())
+                .spawn(move || {
+                    while wait_for_interval(&stop, interval) {
+                        if let Err(error) = pidfile.renew(lease) {
+                            let lost = error.kind() == io::ErrorKind::AddrInUse;
+                            crate::diag::warn!(path=%pidfile.path.display(), "Unable to renew the lease on PID file {path}: {error}", path = pidfile.path.display());
+                            on_error(error);
+                            if lost {
+                                break;
+                            }
+                        }
+                    }
+                })?
+        };
+
+        Ok(LeaseRenewal {
+            pidfile,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Wait for `interval` to pass, returning `false` if the renewal thread is stopped first.
+fn wait_for_interval(stop: &(Mutex<bool>, Condvar), interval: Duration) -> bool {
+    let (stopped, wakeup) = stop;
+    let deadline = Instant::now() + interval;
+
+    // Wait for the whole interval, even if the condition variable wakes us up spuriously.
+    let mut stopped = stopped.lock().unwrap_or_else(|error| error.into_inner());
+    while !*stopped {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return true;
+        }
+        stopped = wakeup
+            .wait_timeout(stopped, remaining)
+            .unwrap_or_else(|error| error.into_inner())
+            .0;
+    }
+    false
+}
+
+impl LeaseRenewal {
+    /// Stop renewing the lease, and return the PID file.
+    ///
+    /// The lease is left to run out, unless it is renewed again with [`PidFile::renew`].
+    pub fn into_inner(mut self) -> PidFile {
+        self.stop();
+        let pidfile = Arc::clone(&self.pidfile);
+        drop(self);
+        match Arc::try_unwrap(pidfile) {
+            Ok(pidfile) => pidfile,
+            Err(_) => unreachable!("the renewal thread has exited"),
+        }
+    }
+
+    /// Signal the renewal thread to stop, and wait for it to exit.
+    fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+
+        let (stopped, wakeup) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|error| error.into_inner()) = true;
+        wakeup.notify_all();
+        if thread.join().is_err() {
+            crate::diag::error!(path=%self.pidfile.path.display(), "Lease renewal thread for PID file {path} panicked", path = self.pidfile.path.display());
+        }
+    }
+}
+
+impl Deref for LeaseRenewal {
+    type Target = PidFile;
+
+    fn deref(&self) -> &PidFile {
+        &self.pidfile
+    }
+}
+
+impl Drop for LeaseRenewal {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn expires(path: &std::path::Path) -> u64 {
+        PidFile::read_field(path, "expires")
+            .unwrap()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_renew_in_background() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("renewal.pid");
+        let lease = Duration::from_secs(2);
+
+        let pidfile = PidFile::builder(&path).lease(lease).create().unwrap();
+        let error = PidFile::builder(tmp.path().join("other.pid"))
+            .create()
+            .unwrap()
+            .renew_in_background(lease, lease, |_| {})
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+        let created = expires(&path);
+        let renewal = pidfile
+            .renew_in_background(lease, Duration::from_millis(20), |error| {
+                panic!("unexpected renewal error: {error}")
+            })
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(expires(&path) > created);
+        assert!(renewal.path().exists());
+
+        let pidfile = renewal.into_inner();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pidfile);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_renewal_lost() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("renewal-lost.pid");
+        let lease = Duration::from_secs(60);
+
+        let (tx, rx) = mpsc::channel();
+        let renewal = PidFile::builder(&path)
+            .lease(lease)
+            .create()
+            .unwrap()
+            .renew_in_background(lease, Duration::from_millis(10), move |error| {
+                let _ = tx.send(error.kind());
+            })
+            .unwrap();
+
+        std::fs::write(&path, "1").unwrap();
+        let kind = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(kind, io::ErrorKind::AddrInUse);
+
+        // Renewals stopped after the lock was lost, so the thread has dropped the sender.
+        assert!(rx.recv_timeout(Duration::from_secs(10)).is_err());
+        std::fs::write(&path, std::process::id().to_string()).unwrap();
+        drop(renewal);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,246 @@
+//! Periodic self-consistency checks for held PID files.
+
+use std::io;
+
+use crate::contents::Contents;
+use crate::pidfile::Owner;
+use crate::registry::Registration;
+use crate::{info_owner, is_invalid, CheckPolicy, PidFile};
+
+/// What [`PidFile::verify_and_repair`] found on disk, and what it did about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Repair {
+    /// The PID file contains our PID, and was left alone.
+    Intact,
+
+    /// The PID file had been removed, and was recreated.
+    Recreated,
+
+    /// The PID file was empty or did not contain a valid PID, and was rewritten.
+    Rewritten,
+
+    /// The PID file contained the PID of a process which is no longer running, and was
+    /// rewritten.
+    Replaced(libc::pid_t),
+
+    /// The PID file is now held by the running process with this PID, and was left alone.
+    Taken(libc::pid_t),
+}
+
+impl Repair {
+    /// Whether this process holds the PID file after the check.
+    pub fn is_held(&self) -> bool {
+        !matches!(self, Repair::Taken(_))
+    }
+}
+
+impl PidFile {
+    /// Check that this PID file still exists on disk and contains our PID, and repair it if
+    /// it doesn't.
+    ///
+    /// This is meant to be called periodically by long-running daemons, since PID files can
+    /// be removed by `/tmp` cleaners or overwritten by careless operators. A PID file which
+    /// is missing, invalid, or held by a process which is no longer running is rewritten
+    /// with our PID. A PID file which is held by another running process is never touched,
+    /// and is reported as [`Repair::Taken`], so the caller can decide whether to exit, as
+    /// well as to the callback set with [`Builder::on_lock_lost`](crate::Builder::on_lock_lost).
+    ///
+    /// Repairs write back the contents which this process wrote when it created the PID file,
+    /// so metadata such as the magic value and instance ID is kept, while fields updated
+    /// since, such as the heartbeat, are as they were then. PID files
+    /// from [`PidFile::new_shared`] can't be repaired, and return an error with
+    /// [`io::ErrorKind::Unsupported`].
+    pub fn verify_and_repair(&mut self) -> Result<Repair, io::Error> {
+        let _span = crate::diag::debug_span!("pidfile", path = %self.path.display()).entered();
+        self.check_exclusive("repaired")?;
+
+        let repair = match self.contents() {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Repair::Recreated,
+            Err(error) => return Err(error),
+            Ok(info) if self.contains_own_pid(&info) => return Ok(Repair::Intact),
+            Ok(info) => match info_owner(&self.path, &info, &CheckPolicy::default()) {
+                Ok(Owner::Alive(pid) | Owner::Current(pid)) => {
+                    self.lock_lost(pid);
+                    return Ok(Repair::Taken(pid));
+                }
+                Ok(Owner::Dead(pid) | Owner::Previous(pid) | Owner::Settling(pid, _)) => {
+                    Repair::Replaced(pid)
+                }
+                Ok(Owner::Absent) => Repair::Recreated,
+                Ok(Owner::Empty) => Repair::Rewritten,
+                Err(error) if is_invalid(&error) => Repair::Rewritten,
+                Err(error) => return Err(error),
+            },
+        };
+
+        self.replace_contents(&self.written)?;
+        if let Some(registration) = &self._registration {
+            registration.record_file_id();
+        }
+        crate::diag::warn!(path=%self.path.display(), ?repair, "Repaired PID file {path}", path = self.path.display());
+        Ok(repair)
+    }
+
+    /// Check that the path of this PID file still reaches the file we created, and recreate
+    /// it if it doesn't.
+    ///
+    /// In containers, the directory holding a PID file, such as `/run`, can be remounted, or
+    /// another file bind-mounted over the PID file, so that the path no longer reaches the
+    /// file we created, even though nothing removed it. This compares the device and inode
+    /// at the path with those recorded when the PID file was created, which is cheaper than
+    /// reading it, and only falls back to [`PidFile::verify_and_repair`] when they differ,
+    /// or aren't available on this platform. The same rules apply: a PID file which is
+    /// missing, invalid or stale is rewritten with our PID, while one held by another
+    /// running process is left alone and reported as [`Repair::Taken`]. A different file
+    /// which contains our PID is adopted, and reported as [`Repair::Intact`].
+    ///
+    /// PID files created with [`PidFile::new_at`], [`PidFile::from_fd`],
+    /// [`PidFile::new_shared`], [`Builder::fcntl_lock`](crate::Builder::fcntl_lock) or
+    /// [`Builder::lock_dir`](crate::Builder::lock_dir) can't be recreated at their path, and
+    /// return an error with [`io::ErrorKind::Unsupported`].
+    pub fn relink_if_missing(&mut self) -> Result<Repair, io::Error> {
+        #[cfg(unix)]
+        let relative = self.dir.is_some() || self.lock_file.is_some();
+        #[cfg(not(unix))]
+        let relative = false;
+
+        if relative || self.shared || self.lock_dir.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only PID files created at a path can be relinked",
+            ));
+        }
+
+        let linked = self
+            ._registration
+            .as_ref()
+            .and_then(Registration::is_linked);
+        if linked == Some(true) {
+            return Ok(Repair::Intact);
+        }
+
+        let repair = self.verify_and_repair()?;
+        if repair == Repair::Intact {
+            if let Some(registration) = &self._registration {
+                registration.record_file_id();
+            }
+        }
+        Ok(repair)
+    }
+
+    fn contains_own_pid(&self, info: &str) -> bool {
+        Contents::parse(&self.path, info).is_ok_and(|contents| contents.pid() == self.pid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    fn repair(pid_file: &mut PidFile) -> Repair {
+        silenced(|| pid_file.verify_and_repair().unwrap())
+    }
+
+    #[test]
+    fn test_verify_and_repair() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("repair.pid");
+        let mut pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(repair(&mut pid_file), Repair::Intact);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repair(&mut pid_file), Repair::Recreated);
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        std::fs::write(&path, "garbage").unwrap();
+        assert_eq!(repair(&mut pid_file), Repair::Rewritten);
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead = child.id() as libc::pid_t;
+        child.wait().unwrap();
+        std::fs::write(&path, dead.to_string()).unwrap();
+        assert_eq!(repair(&mut pid_file), Repair::Replaced(dead));
+        assert_eq!(repair(&mut pid_file), Repair::Intact);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let alive = child.id() as libc::pid_t;
+        std::fs::write(&path, alive.to_string()).unwrap();
+        let taken = repair(&mut pid_file);
+        assert_eq!(taken, Repair::Taken(alive));
+        assert!(!taken.is_held());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), alive.to_string());
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        std::fs::write(&path, std::process::id().to_string()).unwrap();
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_repair_keeps_contents() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("repair-contents.pid");
+        let mut pid_file = PidFile::builder(&path)
+            .magic("repair")
+            .instance_id("repair-1")
+            .create()
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("magic=repair"));
+        pid_file.heartbeat().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repair(&mut pid_file), Repair::Recreated);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), written);
+        drop(pid_file);
+
+        let mut shared = PidFile::new_shared(&path).unwrap();
+        let error = shared.verify_and_repair().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        drop(shared);
+    }
+
+    #[test]
+    fn test_relink_if_missing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("relink.pid");
+        let relink = |pid_file: &mut PidFile| silenced(|| pid_file.relink_if_missing().unwrap());
+
+        let mut pid_file = PidFile::new(&path).unwrap();
+        assert_eq!(relink(&mut pid_file), Repair::Intact);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(relink(&mut pid_file), Repair::Recreated);
+        assert!(PidFile::is_locked(&path).unwrap());
+
+        // Another file with our PID, as if bind-mounted over the PID file, is adopted.
+        let copy = tmp.path().join("copy.pid");
+        std::fs::write(&copy, std::process::id().to_string()).unwrap();
+        std::fs::rename(&copy, &path).unwrap();
+        assert_eq!(relink(&mut pid_file), Repair::Intact);
+        let error = PidFile::new(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let alive = child.id() as libc::pid_t;
+        std::fs::write(&copy, alive.to_string()).unwrap();
+        std::fs::rename(&copy, &path).unwrap();
+        assert_eq!(relink(&mut pid_file), Repair::Taken(alive));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), alive.to_string());
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(matches!(relink(&mut pid_file), Repair::Replaced(pid) if pid == alive));
+        assert_eq!(relink(&mut pid_file), Repair::Intact);
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
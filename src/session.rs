@@ -0,0 +1,84 @@
+//! The process group and session of the process which owns a PID file.
+
+use std::io;
+use std::path::Path;
+
+use crate::{pid_file_owner, CheckPolicy, PidFile};
+
+/// Call `getpgid` or `getsid` for `pid`, returning `None` if the process is gone or hidden.
+fn process_id(
+    pid: libc::pid_t,
+    query: unsafe extern "C" fn(libc::pid_t) -> libc::pid_t,
+) -> Result<Option<libc::pid_t>, io::Error> {
+    // SAFETY: `getpgid` and `getsid` only read the process table.
+    #[allow(unsafe_code)]
+    let id = unsafe { query(pid) };
+
+    if id >= 0 {
+        return Ok(Some(id));
+    }
+
+    let error = io::Error::last_os_error();
+    match error.raw_os_error() {
+        // ESRCH: the process has exited. EPERM: the process is in another session, and
+        // this platform doesn't report on those.
+        Some(libc::ESRCH | libc::EPERM) => Ok(None),
+        _ => Err(error),
+    }
+}
+
+impl PidFile {
+    /// The process group of the process which holds the PID file at this path.
+    ///
+    /// Compare this with the process group of this process to tell whether the owner is
+    /// part of the same job, e.g. a child which this process spawned, rather than an
+    /// unrelated process which happens to have the same PID.
+    ///
+    /// Returns `Ok(None)` when there is no PID file, when its owner is no longer running,
+    /// or when the process group of the owner is unavailable.
+    pub fn owner_pgid(path: &Path) -> Result<Option<libc::pid_t>, io::Error> {
+        match pid_file_owner(path, &CheckPolicy::default())?.alive() {
+            Some(pid) => process_id(pid, libc::getpgid),
+            None => Ok(None),
+        }
+    }
+
+    /// The session of the process which holds the PID file at this path.
+    ///
+    /// Returns `Ok(None)` when there is no PID file, when its owner is no longer running,
+    /// or when the session of the owner is unavailable (some platforms don't report the
+    /// sessions of processes in other sessions).
+    pub fn owner_sid(path: &Path) -> Result<Option<libc::pid_t>, io::Error> {
+        match pid_file_owner(path, &CheckPolicy::default())?.alive() {
+            Some(pid) => process_id(pid, libc::getsid),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_owner_session() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("pidfile-session.pid");
+        assert_eq!(PidFile::owner_pgid(&path).unwrap(), None);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, format!("{}", child.id())).unwrap();
+
+        let ours = process_id(0, libc::getpgid).unwrap();
+        assert_eq!(PidFile::owner_pgid(&path).unwrap(), ours);
+        let ours = process_id(0, libc::getsid).unwrap();
+        assert_eq!(PidFile::owner_sid(&path).unwrap(), ours);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert_eq!(PidFile::owner_sid(&path).unwrap(), None);
+    }
+}
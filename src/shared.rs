@@ -0,0 +1,286 @@
+//! PID files held jointly by several processes, for reader-writer style coordination.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::contents::Contents;
+use crate::registry::Registration;
+use crate::sidecar::{SidecarLock, LOCK_WAIT};
+use crate::{current_pid, info_owner, pid_in_use, reclaim, CheckPolicy, PidFile};
+
+/// The metadata field which lists the PIDs of the shared holders of a PID file.
+const SHARED_FIELD: &str = "shared";
+
+/// The shared holders listed in a PID file, or `None` if it is held exclusively.
+pub(crate) fn holders(contents: &Contents) -> Option<Vec<libc::pid_t>> {
+    let field = contents.field(SHARED_FIELD)?;
+    Some(
+        field
+            .split(',')
+            .filter_map(|pid| pid.trim().parse().ok())
+            .collect(),
+    )
+}
+
+/// The first shared holder listed in a PID file which is still running, other than `except`.
+pub(crate) fn live_holder(
+    contents: &Contents,
+    except: libc::pid_t,
+) -> Result<Option<libc::pid_t>, io::Error> {
+    for pid in holders(contents).unwrap_or_default() {
+        if pid != except && pid_in_use(pid)? {
+            return Ok(Some(pid));
+        }
+    }
+    Ok(None)
+}
+
+/// Contents for a PID file shared by `holders`, which must not be empty.
+///
+/// The first holder is written on the first line, so that readers which only parse the PID
+/// always see a running process.
+fn render(holders: &[libc::pid_t]) -> String {
+    let mut contents = Contents::new(holders[0]);
+    let list: Vec<String> = holders.iter().map(|pid| pid.to_string()).collect();
+    contents.set_field(SHARED_FIELD, list.join(","));
+    contents.render()
+}
+
+/// Atomically replace the contents of the shared PID file at `path`.
+fn replace(path: &Path, pid: libc::pid_t, contents: &str) -> Result<(), io::Error> {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{pid}.tmp"));
+    let tmp = path.with_file_name(name);
+    crate::mode::replace(path, &tmp, contents)
+}
+
+/// The holders in `listed` which are still running, other than `except`.
+fn still_running(
+    listed: Vec<libc::pid_t>,
+    except: libc::pid_t,
+) -> Result<Vec<libc::pid_t>, io::Error> {
+    let mut running = Vec::with_capacity(listed.len());
+    for holder in listed {
+        if holder != except && pid_in_use(holder)? {
+            running.push(holder);
+        }
+    }
+    Ok(running)
+}
+
+/// The running shared holders of the PID file at `path`, checking the existing contents.
+///
+/// Fails if the PID file is held exclusively by a running process.
+fn running_holders(path: &Path, pid: libc::pid_t) -> Result<Vec<libc::pid_t>, io::Error> {
+    let info = match std::fs::read_to_string(path) {
+        Ok(info) => info,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let listed = Contents::parse(path, &info)
+        .ok()
+        .and_then(|contents| holders(&contents));
+    match listed {
+        Some(listed) => still_running(listed, pid),
+        None => {
+            // The file is rewritten in place below, so there is nothing to remove.
+            let owner = info_owner(path, &info, &CheckPolicy::default());
+            reclaim(path, owner)?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+impl PidFile {
+    /// Join the shared holders of the PID file at `path`.
+    ///
+    /// Any number of processes can hold a PID file in shared mode at once, like readers of
+    /// a reader-writer lock, while a process which acquires it with [`PidFile::new`] holds
+    /// it exclusively, like the writer. The PIDs of the shared holders are listed in the
+    /// `shared` field of the PID file. This fails with [`io::ErrorKind::AddrInUse`] while
+    /// the PID file is held exclusively by a running process, and creating the PID file
+    /// exclusively fails in the same way while any shared holder is still running.
+    ///
+    /// When a shared holder is dropped, it removes its PID from the list, and the PID file
+    /// is only removed when the last running holder drops it. Holders which are no longer
+    /// running are pruned from the list whenever it is updated.
+    ///
+    /// # Consistency
+    ///
+    /// The list of holders is read, checked and atomically replaced under the lock file
+    /// beside the PID file, the same one which [`PidFile::new`] holds while it creates or
+    /// replaces the PID file. Holders which join or leave at the same moment take turns, so
+    /// no update is lost, and a process acquiring the PID file exclusively sees either the
+    /// old list or the new one. On platforms without `flock`, updates aren't serialized,
+    /// and concurrent updates can overwrite each other.
+    ///
+    /// Since the other holders rewrite the PID file as they join and leave, methods which
+    /// rewrite or repair it, such as [`PidFile::heartbeat`], [`PidFile::touch`],
+    /// [`PidFile::verify_and_repair`] and [`PidFile::rename`], return an error with
+    /// [`io::ErrorKind::Unsupported`] for shared PID files. Methods which only read it, such
+    /// as [`PidFile::age`], accept any PID file which lists this process as a holder. Shared
+    /// PID files aren't removed by `SignalCleanup`.
+    pub fn new_shared(path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        let path = path.into();
+        let _span = crate::diag::debug_span!("pidfile", path = %path.display()).entered();
+
+        let registration = Registration::new(&path)?;
+        let pid = current_pid()?;
+        let lock = SidecarLock::acquire(&path, Instant::now() + LOCK_WAIT)?;
+        let mut joining = running_holders(&path, pid)?;
+        joining.push(pid);
+        replace(&path, pid, &render(&joining))?;
+        drop(lock);
+        crate::diag::trace!(%pid, path=%path.display(), "Joined {count} shared holders of PID file {path}", count = joining.len(), path = path.display());
+
+        let mut pidfile = PidFile::locked(path, pid, Some(registration));
+        pidfile.shared = true;
+        #[cfg(all(unix, feature = "signal-cleanup"))]
+        {
+            pidfile.published = None;
+        }
+        Ok(pidfile)
+    }
+
+    /// Remove this process from the shared holders of this PID file, removing the PID file
+    /// if there are no other running holders.
+    ///
+    /// A PID file which doesn't list this process anymore is left alone.
+    pub(crate) fn leave_shared(&self) -> Result<(), io::Error> {
+        let _lock = SidecarLock::acquire(&self.path, Instant::now() + LOCK_WAIT)?;
+        let info = match std::fs::read_to_string(&self.path) {
+            Ok(info) => info,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        let listed = Contents::parse(&self.path, &info)
+            .ok()
+            .and_then(|contents| holders(&contents));
+        match listed {
+            Some(listed) if listed.contains(&self.pid) => {
+                let running = still_running(listed, self.pid)?;
+                if running.is_empty() {
+                    std::fs::remove_file(&self.path)
+                } else {
+                    replace(&self.path, self.pid, &render(&running))
+                }
+            }
+            _ => {
+                crate::diag::warn!(path=%self.path.display(), "PID File {path} no longer lists this process as a shared holder", path = self.path.display());
+                Ok(())
+            }
+        }
+    }
+
+    /// Fail with [`io::ErrorKind::Unsupported`] if this PID file is shared, since the other
+    /// holders rewrite it as they join and leave.
+    pub(crate) fn check_exclusive(&self, action: &str) -> Result<(), io::Error> {
+        if self.shared {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("shared PID files can't be {action}"),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quiet::silenced;
+
+    #[test]
+    fn test_new_shared() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("shared.pid");
+        let pid = std::process::id() as libc::pid_t;
+
+        let shared = PidFile::new_shared(&path).unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        let error = shared.heartbeat().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        drop(shared);
+        assert!(!path.exists());
+
+        let mut reader = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let reader_pid = reader.id() as libc::pid_t;
+        std::fs::write(&path, format!("{reader_pid}\nshared={reader_pid}\n")).unwrap();
+
+        let shared = PidFile::new_shared(&path).unwrap();
+        let contents = Contents::parse(&path, &shared.contents().unwrap()).unwrap();
+        assert_eq!(holders(&contents), Some(vec![reader_pid, pid]));
+        drop(shared);
+        let contents = Contents::parse(&path, &std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holders(&contents), Some(vec![reader_pid]));
+
+        reader.kill().unwrap();
+        reader.wait().unwrap();
+        let shared = PidFile::new_shared(&path).unwrap();
+        let contents = Contents::parse(&path, &shared.contents().unwrap()).unwrap();
+        assert_eq!(holders(&contents), Some(vec![pid]));
+        drop(shared);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shared_waits_for_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("shared-lock.pid");
+
+        let lock = SidecarLock::acquire(&path, Instant::now()).unwrap();
+        let joining = std::thread::spawn({
+            let path = path.clone();
+            move || PidFile::new_shared(path).unwrap()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!path.exists());
+
+        drop(lock);
+        let shared = joining.join().unwrap();
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(shared);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_shared_excludes_writer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("shared-writer.pid");
+
+        let mut dead = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = dead.id();
+        dead.wait().unwrap();
+        let mut reader = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let reader_pid = reader.id();
+
+        // The first holder has exited, but another is still running.
+        std::fs::write(
+            &path,
+            format!("{dead_pid}\nshared={dead_pid},{reader_pid}\n"),
+        )
+        .unwrap();
+        let error = silenced(|| PidFile::new(&path).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        std::fs::write(&path, reader_pid.to_string()).unwrap();
+        let error = silenced(|| PidFile::new_shared(&path).unwrap_err());
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+        reader.kill().unwrap();
+        reader.wait().unwrap();
+        let writer = silenced(|| PidFile::new(&path).unwrap());
+        drop(writer);
+    }
+}
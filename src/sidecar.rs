@@ -0,0 +1,256 @@
+//! The lock file beside each PID file, which serializes the processes creating or replacing it.
+
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::fs::File;
+use std::io;
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use crate::at::c_path;
+#[cfg(unix)]
+use crate::quiet::in_use;
+
+/// How long to wait for another process to finish creating or replacing a PID file, when the
+/// caller has no deadline of its own.
+pub(crate) const LOCK_WAIT: Duration = Duration::from_secs(1);
+
+/// How often to try the lock again while another process holds it.
+#[cfg(unix)]
+const LOCK_POLL: Duration = Duration::from_millis(1);
+
+/// An exclusive `flock` on the lock file beside a PID file, `.{name}.lock`, held while the PID
+/// file is created, or while a stale PID file is checked again and replaced.
+///
+/// Checking the owner of an existing PID file may run callbacks (e.g.
+/// [`Builder::health_check`](crate::Builder::health_check)), so it is checked without the
+/// lock, and then checked to be unchanged under the lock before it is replaced. The lock is
+/// only ever held across a few filesystem operations, so a process which finds it held
+/// retries without blocking until its deadline. The lock file is removed when the lock is
+/// released.
+///
+/// Together with [`SidecarLock::claim`], this is the only way that PID files are replaced:
+/// a stale PID file is never removed by its path, which could remove a PID file which
+/// another process has just created there.
+#[cfg(unix)]
+#[derive(Debug)]
+pub(crate) struct SidecarLock<'a> {
+    dir: Option<BorrowedFd<'a>>,
+    name: CString,
+    _file: File,
+}
+
+/// `flock` is unavailable on this platform, so PID files are replaced without a lock.
+#[cfg(not(unix))]
+#[derive(Debug)]
+pub(crate) struct SidecarLock<'a>(std::marker::PhantomData<&'a ()>);
+
+#[cfg(not(unix))]
+impl SidecarLock<'static> {
+    /// Lock the PID file at `path`, which does nothing on this platform.
+    pub(crate) fn acquire(_path: &Path, _deadline: Instant) -> Result<Self, io::Error> {
+        Ok(SidecarLock(std::marker::PhantomData))
+    }
+}
+
+impl SidecarLock<'_> {
+    /// Replace the PID file with `replace`, if `read` still finds what was `found` when the
+    /// PID file was checked: its contents, or `None` when there was no PID file.
+    ///
+    /// Returns `Ok(None)` without calling `replace` if another process has changed the PID
+    /// file since it was checked, so that it can be checked again.
+    pub(crate) fn claim<T>(
+        self,
+        found: &Option<Vec<u8>>,
+        read: impl FnOnce() -> Result<Option<Vec<u8>>, io::Error>,
+        replace: impl FnOnce() -> Result<T, io::Error>,
+    ) -> Result<Option<T>, io::Error> {
+        if read()? != *found {
+            return Ok(None);
+        }
+        replace().map(Some)
+    }
+}
+
+#[cfg(unix)]
+impl SidecarLock<'static> {
+    /// Lock the PID file at `path`, waiting until `deadline` for another process to release it.
+    pub(crate) fn acquire(path: &Path, deadline: Instant) -> Result<Self, io::Error> {
+        SidecarLock::acquire_at(None, path, deadline)
+    }
+}
+
+#[cfg(unix)]
+impl<'a> SidecarLock<'a> {
+    /// Lock the PID file at `path`, relative to `dir` when there is one, waiting until
+    /// `deadline` for another process to release it.
+    ///
+    /// Fails with [`io::ErrorKind::AddrInUse`] if the lock is still held at `deadline`, and
+    /// with the underlying error if the lock file can't be created or locked.
+    pub(crate) fn acquire_at(
+        dir: Option<BorrowedFd<'a>>,
+        path: &Path,
+        deadline: Instant,
+    ) -> Result<Self, io::Error> {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        name.push(".lock");
+        let name = c_path(&path.with_file_name(name))?;
+        let dirfd = dir.map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd());
+
+        loop {
+            let file = open(dirfd, &name)?;
+            wait_for_lock(&file, path, deadline)?;
+
+            // The previous holder removes the lock file as it releases it, so we may have
+            // locked a file which is no longer at its path.
+            if is_linked(dirfd, &name, &file)? {
+                return Ok(SidecarLock {
+                    dir,
+                    name,
+                    _file: file,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SidecarLock<'_> {
+    fn drop(&mut self) {
+        let dirfd = self.dir.map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd());
+        // SAFETY: `name` is a valid C string, and `dirfd` is a valid file descriptor (or
+        // `AT_FDCWD`) for the duration of this call.
+        #[allow(unsafe_code)]
+        let result = unsafe { libc::unlinkat(dirfd, self.name.as_ptr(), 0) };
+        if result != 0 {
+            let error = io::Error::last_os_error();
+            crate::diag::debug!(name = ?self.name, "Unable to remove lock file {name:?}: {error}", name = self.name);
+        }
+    }
+}
+
+/// Open (or create) the lock file `name` relative to `dirfd`.
+///
+/// Locking only needs read access, so other users' lock files can be locked too.
+#[cfg(unix)]
+fn open(dirfd: libc::c_int, name: &CString) -> Result<File, io::Error> {
+    // SAFETY: `name` is a valid C string, and `dirfd` is a valid file descriptor (or
+    // `AT_FDCWD`) for the duration of this call.
+    #[allow(unsafe_code)]
+    let fd = unsafe {
+        libc::openat(
+            dirfd,
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_CREAT | libc::O_CLOEXEC | libc::O_NOFOLLOW,
+            0o644 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `openat` returned a new file descriptor which nothing else owns.
+    #[allow(unsafe_code)]
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Take an exclusive `flock` on `file` without blocking, retrying until `deadline` while
+/// another process holds it.
+#[cfg(unix)]
+fn wait_for_lock(file: &File, path: &Path, deadline: Instant) -> Result<(), io::Error> {
+    loop {
+        // SAFETY: `file` is a valid file descriptor for the duration of this call.
+        #[allow(unsafe_code)]
+        let result = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+        if result == 0 {
+            return Ok(());
+        }
+
+        let error = io::Error::last_os_error();
+        match error.kind() {
+            io::ErrorKind::Interrupted => {}
+            io::ErrorKind::WouldBlock => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    in_use!(debug, path=%path.display(), "PID file {path} is being created by another process", path = path.display());
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!(
+                            "PID File {path} is being created by another process",
+                            path = path.display()
+                        ),
+                    ));
+                }
+                std::thread::sleep(remaining.min(LOCK_POLL));
+            }
+            _ => {
+                crate::diag::error!(path=%path.display(), "Unable to lock PID file {path}: {error}", path = path.display());
+                return Err(error);
+            }
+        }
+    }
+}
+
+/// Whether `file` is still the file at `name` relative to `dirfd`.
+#[cfg(unix)]
+fn is_linked(dirfd: libc::c_int, name: &CString, file: &File) -> Result<bool, io::Error> {
+    // SAFETY: `stat` is plain old data, for which all zeroes is a valid value.
+    #[allow(unsafe_code)]
+    let (mut ours, mut linked): (libc::stat, libc::stat) =
+        unsafe { (std::mem::zeroed(), std::mem::zeroed()) };
+
+    // SAFETY: `file` is a valid file descriptor, and `ours` is valid for the call.
+    #[allow(unsafe_code)]
+    if unsafe { libc::fstat(file.as_raw_fd(), &mut ours) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: `name` is a valid C string, `dirfd` is a valid file descriptor (or
+    // `AT_FDCWD`), and `linked` is valid for the call.
+    #[allow(unsafe_code)]
+    let result =
+        unsafe { libc::fstatat(dirfd, name.as_ptr(), &mut linked, libc::AT_SYMLINK_NOFOLLOW) };
+    if result != 0 {
+        let error = io::Error::last_os_error();
+        return match error.kind() {
+            io::ErrorKind::NotFound => Ok(false),
+            _ => Err(error),
+        };
+    }
+    Ok((ours.st_dev, ours.st_ino) == (linked.st_dev, linked.st_ino))
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sidecar_lock() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("sidecar.pid");
+        let lock_path = tmp.path().join(".sidecar.pid.lock");
+
+        let lock = SidecarLock::acquire(&path, Instant::now()).unwrap();
+        assert!(lock_path.exists());
+
+        // `flock` locks conflict between open file descriptions, even within a process.
+        let start = Instant::now();
+        let deadline = start + Duration::from_millis(20);
+        let error = SidecarLock::acquire(&path, deadline).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        drop(lock);
+        assert!(!lock_path.exists());
+        drop(SidecarLock::acquire(&path, Instant::now()).unwrap());
+
+        let missing = tmp.path().join("missing").join("sidecar.pid");
+        let error = SidecarLock::acquire(&missing, Instant::now()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,203 @@
+//! Signalling the processes which own PID files.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::acquired::observed_holder;
+use crate::pidfile::is_invalid;
+use crate::quiet::in_use;
+use crate::{check_pid, current_pid, pid_file_owner, CheckPolicy, Liveness, PidFile, PidFileError};
+
+/// How often to check whether a signalled process has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Send `signal` to the process with the given PID.
+fn send_signal(pid: libc::pid_t, signal: libc::c_int) -> Result<(), io::Error> {
+    // SAFETY: `kill` has no memory safety requirements.
+    #[allow(unsafe_code)]
+    let result = unsafe { libc::kill(pid, signal) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Refuse to signal `pid` if it is this process, which holds the PID file at `path`.
+fn refuse_current(path: &Path, pid: libc::pid_t) -> Result<(), io::Error> {
+    if pid != current_pid()? {
+        return Ok(());
+    }
+
+    in_use!(error, path=%path.display(), %pid, "PID File {path} is held by this process, not signalling it", path = path.display());
+    Err(PidFileError::HeldByThisProcess {
+        path: path.display().to_string(),
+    }
+    .into())
+}
+
+impl PidFile {
+    /// Take over the PID file at this path from its current owner.
+    ///
+    /// If the PID file is held by a running process, it is sent `signal`, and this function
+    /// waits up to `timeout` for it to exit before claiming the PID file with [`PidFile::new`].
+    /// If the owner is still running after `timeout`, this returns an error with
+    /// [`io::ErrorKind::TimedOut`], and the PID file is left untouched.
+    ///
+    /// The owner is checked as it would be by [`PidFile::new`], so a stale PID file whose PID
+    /// has been reused (e.g. after a reboot) is replaced without signalling anything. If the
+    /// PID file is held by this process, this fails with
+    /// [`PidFileError::HeldByThisProcess`] rather than signalling itself.
+    ///
+    /// A process which has exited but has not been reaped by its parent (a zombie) still
+    /// appears to be running, so supervisors stealing from their own children should
+    /// make sure the child is reaped.
+    pub fn steal(
+        path: impl Into<PathBuf>,
+        signal: libc::c_int,
+        timeout: Duration,
+    ) -> Result<Self, io::Error> {
+        let path = path.into();
+        let pid = match pid_file_owner(&path, &CheckPolicy::default()) {
+            Ok(owner) => owner.alive(),
+            // `PidFile::new` replaces an invalid PID file, so there is nothing to signal.
+            Err(error) if is_invalid(&error) => None,
+            Err(error) => return Err(error),
+        };
+
+        if let Some(pid) = pid {
+            refuse_current(&path, pid)?;
+            crate::diag::debug!(%pid, path=%path.display(), "Sending signal {signal} to PID {pid} to steal {path}", path = path.display());
+            send_signal(pid, signal)?;
+
+            let deadline = Instant::now() + timeout;
+            loop {
+                match check_pid(pid) {
+                    Liveness::Dead => break,
+                    Liveness::Unknown(error) => return Err(error),
+                    _ if Instant::now() >= deadline => {
+                        crate::diag::error!(%pid, path=%path.display(), "PID {pid} did not exit, not stealing {path}", path = path.display());
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("PID {pid} did not exit within {timeout:?}"),
+                        ));
+                    }
+                    _ => std::thread::sleep(POLL_INTERVAL),
+                }
+            }
+        }
+
+        PidFile::new(path)
+    }
+
+    /// Create a PID file at this path, or signal the process which already holds it.
+    ///
+    /// This is the usual pattern for single-instance applications: the first launch holds
+    /// the PID file, and a second launch sends the first `signal` (e.g. `SIGHUP` to reload,
+    /// or `SIGUSR1` to raise its window) and then exits.
+    ///
+    /// Returns `Ok(Ok(pid_file))` if the PID file was created, and `Ok(Err(()))` if it is
+    /// held by another process, which was sent `signal`. If the owner exits before it can be
+    /// signalled, this tries once more to create the PID file.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use pidfile::PidFile;
+    ///
+    /// # fn main() -> Result<(), std::io::Error> {
+    /// let Ok(pidfile) = PidFile::new_or_signal("/var/run/myapp.pid", libc::SIGUSR1)? else {
+    ///     // The running instance will take it from here.
+    ///     return Ok(());
+    /// };
+    /// # drop(pidfile);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_or_signal(
+        path: impl Into<PathBuf>,
+        signal: libc::c_int,
+    ) -> Result<Result<Self, ()>, io::Error> {
+        let path = path.into();
+        let error = match PidFile::new(&path) {
+            Ok(pid_file) => return Ok(Ok(pid_file)),
+            Err(error) => error,
+        };
+
+        let Some(pid) = observed_holder(&error) else {
+            return Err(error);
+        };
+
+        refuse_current(&path, pid)?;
+        crate::diag::debug!(%pid, path=%path.display(), "Sending signal {signal} to PID {pid}, which holds {path}", path = path.display());
+        match send_signal(pid, signal) {
+            Ok(()) => Ok(Err(())),
+            Err(error) if error.raw_os_error() == Some(libc::ESRCH) => PidFile::new(path).map(Ok),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_steal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("steal.pid");
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, format!("{}", child.id())).unwrap();
+        let reaper = std::thread::spawn(move || child.wait().unwrap());
+
+        let pid_file = PidFile::steal(&path, libc::SIGTERM, Duration::from_secs(10)).unwrap();
+        assert!(!reaper.join().unwrap().success());
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_steal_own() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("steal-own.pid");
+
+        let pid_file = PidFile::new(&path).unwrap();
+        let error = PidFile::steal(&path, libc::SIGTERM, Duration::from_secs(10)).unwrap_err();
+        assert_eq!(
+            error.get_ref().unwrap().downcast_ref::<PidFileError>(),
+            Some(&PidFileError::HeldByThisProcess {
+                path: path.display().to_string()
+            })
+        );
+        assert!(PidFile::is_locked(&path).unwrap());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_new_or_signal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("new-or-signal.pid");
+
+        let pid_file = PidFile::new_or_signal(&path, libc::SIGTERM)
+            .unwrap()
+            .unwrap();
+        drop(pid_file);
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        std::fs::write(&path, format!("{}", child.id())).unwrap();
+
+        assert!(PidFile::new_or_signal(&path, libc::SIGTERM)
+            .unwrap()
+            .is_err());
+        assert!(!child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,49 @@
+//! Bounding the latency of filesystem operations which may block.
+
+use std::io;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Run `operation` on a worker thread, waiting at most `timeout` for it to finish.
+///
+/// If the operation doesn't finish in time, this returns an error with
+/// [`io::ErrorKind::TimedOut`]. The worker thread can't be cancelled, so the
+/// operation may still be in flight (and may still complete) after this returns.
+pub(crate) fn with_timeout<T, F>(timeout: Duration, operation: F) -> Result<T, io::Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, io::Error> + Send + 'static,
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+    std::thread::Builder::new()
+        .name("pidfile-timeout".into())
+        .spawn(move || {
+            let _ = tx.send(operation());
+        })?;
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("operation did not complete within {timeout:?}"),
+        )),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(io::Error::other("operation panicked")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_with_timeout() {
+        assert_eq!(with_timeout(Duration::from_secs(10), || Ok(1)).unwrap(), 1);
+
+        let error = with_timeout(Duration::from_millis(10), || {
+            std::thread::sleep(Duration::from_secs(1));
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
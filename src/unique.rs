@@ -0,0 +1,58 @@
+//! PID files at unique, generated paths, for tests and isolation.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::contents::Contents;
+use crate::registry::Registration;
+use crate::{current_pid, PidFile};
+
+impl PidFile {
+    /// Create a new PID file for this process at a unique path in the system's temporary directory.
+    ///
+    /// See [`PidFile::new_unique_in`] for details.
+    pub fn new_unique() -> Result<Self, io::Error> {
+        Self::new_unique_in(std::env::temp_dir())
+    }
+
+    /// Create a new PID file for this process at a unique path in `dir`.
+    ///
+    /// The file name contains this process's PID and a random suffix, and the file is
+    /// created exclusively, so it is guaranteed not to collide with any other PID file.
+    /// This is useful for tests which run in parallel and want lock semantics, without
+    /// coordinating paths. Use [`PidFile::path`] to find the generated path.
+    pub fn new_unique_in(dir: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let pid = current_pid()?;
+        let (mut file, path) = tempfile::Builder::new()
+            .prefix(&format!("pidfile-{pid}-"))
+            .suffix(".pid")
+            .tempfile_in(dir)?
+            .keep()
+            .map_err(|error| error.error)?;
+
+        let registration = Registration::new(&path)?;
+        let pidfile = PidFile::locked(path, pid, Some(registration));
+        file.write_all(Contents::new(pid).render().as_bytes())?;
+        crate::diag::trace!(%pid, path=%pidfile.path.display(), "Locked PID file at {path}", path = pidfile.path.display());
+        Ok(pidfile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_unique() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = PidFile::new_unique_in(tmp.path()).unwrap();
+        let b = PidFile::new_unique_in(tmp.path()).unwrap();
+        assert_ne!(a.path(), b.path());
+        assert!(PidFile::is_locked(a.path()).unwrap());
+        assert!(PidFile::is_locked(b.path()).unwrap());
+
+        let path = a.path().to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
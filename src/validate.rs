@@ -0,0 +1,182 @@
+//! Read-only validation of PID files, for health checks and tests.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use crate::contents::Contents;
+use crate::pidfile::Owner;
+use crate::{hostname, info_owner, is_invalid, lease_expired, CheckPolicy, PidFile};
+
+/// The reason a PID file failed [`PidFile::validate`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// There is no PID file at the path.
+    Missing,
+
+    /// The PID file does not contain a valid PID.
+    Unparseable(io::Error),
+
+    /// The process which owns the PID file is no longer running.
+    OwnerDead(libc::pid_t),
+
+    /// The lease on the PID file (see [`Builder::lease`](crate::Builder::lease)) has expired.
+    LeaseExpired(libc::pid_t),
+
+    /// The PID file was written on another host, so its owner can't be checked.
+    Foreign(String),
+
+    /// The PID file or its owner could not be checked.
+    Io(io::Error),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::Missing => write!(f, "PID file is missing"),
+            ValidationError::Unparseable(error) => write!(f, "PID file is invalid: {error}"),
+            ValidationError::OwnerDead(pid) => write!(f, "PID {pid} is no longer running"),
+            ValidationError::LeaseExpired(pid) => write!(f, "lease for PID {pid} has expired"),
+            ValidationError::Foreign(host) => write!(f, "PID file belongs to host {host}"),
+            ValidationError::Io(error) => write!(f, "unable to check PID file: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ValidationError::Unparseable(error) | ValidationError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl PidFile {
+    /// Validate that the PID file at this path exists and that its owner is still running.
+    ///
+    /// This never modifies the PID file, so it is suitable for health checks run by
+    /// processes other than the one which holds the lock. When the PID file records
+    /// the host it was written on (see [`Builder::record_host`](crate::Builder::record_host)),
+    /// PID files from other hosts are reported as [`ValidationError::Foreign`], since
+    /// their owners can't be checked from here.
+    ///
+    /// Otherwise, the owner is checked as it would be by [`PidFile::is_locked`], so PID files
+    /// from before the last boot, and shared PID files whose holders have all exited, are
+    /// reported as [`ValidationError::OwnerDead`].
+    pub fn validate(path: &Path) -> Result<(), ValidationError> {
+        let info = std::fs::read_to_string(path).map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => ValidationError::Missing,
+            _ => ValidationError::Io(error),
+        })?;
+
+        // Parsing would fail first, with a less helpful error.
+        if info.trim().is_empty() {
+            return Err(empty());
+        }
+        let contents = Contents::parse(path, &info).map_err(ValidationError::Unparseable)?;
+
+        if lease_expired(&contents).map_err(ValidationError::Unparseable)? {
+            return Err(ValidationError::LeaseExpired(contents.pid()));
+        }
+
+        if let Some(host) = contents.field("host") {
+            if host != hostname().map_err(ValidationError::Io)? {
+                return Err(ValidationError::Foreign(host.to_owned()));
+            }
+        }
+
+        // The rest of the checks are the ones made when acquiring the PID file, so that this
+        // agrees with `PidFile::is_locked` about which PID files are stale.
+        match info_owner(path, &info, &CheckPolicy::default()) {
+            Ok(Owner::Alive(_) | Owner::Current(_)) => Ok(()),
+            Ok(Owner::Dead(pid) | Owner::Previous(pid) | Owner::Settling(pid, _)) => {
+                Err(ValidationError::OwnerDead(pid))
+            }
+            Ok(Owner::Absent) => Err(ValidationError::Missing),
+            Ok(Owner::Empty) => Err(empty()),
+            Err(error) if is_invalid(&error) => Err(ValidationError::Unparseable(error)),
+            Err(error) => Err(ValidationError::Io(error)),
+        }
+    }
+}
+
+/// The error for an empty PID file, e.g. one left by a process which crashed while writing it.
+fn empty() -> ValidationError {
+    ValidationError::Unparseable(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "PID file is empty",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Builder;
+
+    #[test]
+    fn test_validate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("validate.pid");
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::Missing)
+        ));
+
+        let pid_file = Builder::new(&path).record_host(true).create().unwrap();
+        PidFile::validate(&path).unwrap();
+        drop(pid_file);
+
+        std::fs::write(&path, "not a pid").unwrap();
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::Unparseable(_))
+        ));
+
+        std::fs::write(&path, "").unwrap();
+        match PidFile::validate(&path) {
+            Err(ValidationError::Unparseable(error)) => {
+                assert_eq!(error.to_string(), "PID file is empty");
+            }
+            result => panic!("unexpected result for an empty PID file: {result:?}"),
+        }
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let child_pid = child.id() as libc::pid_t;
+        child.wait().unwrap();
+        std::fs::write(&path, format!("{child_pid}")).unwrap();
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::OwnerDead(pid)) if pid == child_pid
+        ));
+
+        // The PID has been reused since the last boot, by a process which is running now.
+        let boot = crate::boot::boot_time().unwrap();
+        let mut reused = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let reused_pid = reused.id() as libc::pid_t;
+        std::fs::write(&path, format!("{reused_pid}\nboot={}\n", boot - 3600)).unwrap();
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::OwnerDead(pid)) if pid == reused_pid
+        ));
+        assert!(!PidFile::is_locked(&path).unwrap());
+        reused.kill().unwrap();
+        reused.wait().unwrap();
+
+        std::fs::write(&path, format!("{}\nhost=elsewhere\n", std::process::id())).unwrap();
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::Foreign(host)) if host == "elsewhere"
+        ));
+
+        std::fs::write(&path, format!("{}\nexpires=0\n", std::process::id())).unwrap();
+        assert!(matches!(
+            PidFile::validate(&path),
+            Err(ValidationError::LeaseExpired(_))
+        ));
+    }
+}
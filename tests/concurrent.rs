@@ -0,0 +1,248 @@
+//! Many processes racing to acquire the same PID file, which needs `fork`, so this runs as
+//! its own test binary, away from the threads of other tests.
+#![cfg(all(unix, feature = "std"))]
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use pidfile::{Builder, PidFile};
+
+/// How many processes race for the PID file in each round.
+const ACQUIRERS: usize = 8;
+
+/// Exit codes for the acquirers which don't acquire the PID file.
+const IN_USE: i32 = 1;
+const FAILED: i32 = 2;
+
+/// Fork a child which runs `acquire` at `start`, and exits with the code it returns.
+fn fork_acquirer(start: Instant, acquire: impl FnOnce() -> i32) -> libc::pid_t {
+    // SAFETY: The child exits without returning to the test harness.
+    #[allow(unsafe_code)]
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0, "fork failed");
+    if pid == 0 {
+        std::thread::sleep(start.saturating_duration_since(Instant::now()));
+        let code = acquire();
+        // SAFETY: Exit immediately, without running the test harness's destructors.
+        #[allow(unsafe_code)]
+        unsafe {
+            libc::_exit(code)
+        };
+    }
+    pid
+}
+
+/// The exit code of a child forked by [`fork_acquirer`], if it has exited.
+fn try_wait(pid: libc::pid_t) -> Option<i32> {
+    let mut status = 0;
+    // SAFETY: `pid` is a child of this process, and `status` is valid for the call.
+    #[allow(unsafe_code)]
+    let waited = unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) };
+    if waited == 0 {
+        return None;
+    }
+    assert_eq!(waited, pid);
+    assert!(libc::WIFEXITED(status));
+    Some(libc::WEXITSTATUS(status))
+}
+
+/// Kill and reap a child forked by [`fork_acquirer`].
+fn kill(pid: libc::pid_t) {
+    // SAFETY: `pid` is a child of this process, which hasn't been reaped yet.
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::kill(pid, libc::SIGKILL);
+        libc::waitpid(pid, std::ptr::null_mut(), 0);
+    }
+}
+
+/// Hold a newly acquired PID file until the round is over, when we are killed by
+/// [`finish_round`].
+///
+/// The holder must outlive every other acquirer, however slowly they start, since its PID
+/// file would be stale once it exits.
+fn hold(pid_file: PidFile) -> i32 {
+    loop {
+        std::thread::sleep(Duration::from_secs(3600));
+        let _ = &pid_file;
+    }
+}
+
+/// Wait for every acquirer but one to exit, check that the last one holds the PID file at
+/// `path`, and kill it.
+///
+/// Returns the PID of the holder, and the exit codes of the others.
+fn finish_round(round: usize, path: &Path, children: &[libc::pid_t]) -> (libc::pid_t, Vec<i32>) {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut running = children.to_vec();
+    let mut codes = Vec::new();
+    while running.len() > 1 {
+        if Instant::now() >= deadline {
+            running.iter().for_each(|pid| kill(*pid));
+            panic!("round {round}: {running:?} are still running, the rest exited with {codes:?}");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        running.retain(|pid| match try_wait(*pid) {
+            Some(code) => {
+                codes.push(code);
+                false
+            }
+            None => true,
+        });
+    }
+
+    let [holder] = running[..] else {
+        panic!("round {round}: no acquirer held the PID file, they exited with {codes:?}");
+    };
+    let held = std::fs::read_to_string(path).unwrap_or_default();
+    kill(holder);
+    assert_eq!(
+        held.lines().next(),
+        Some(holder.to_string().as_str()),
+        "round {round}"
+    );
+    (holder, codes)
+}
+
+#[test]
+fn test_concurrent_acquirers() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("concurrent.pid");
+
+    for round in 0..10 {
+        let _ = std::fs::remove_file(&path);
+        let start = Instant::now() + Duration::from_millis(50);
+
+        let children: Vec<_> = (0..ACQUIRERS)
+            .map(|_| {
+                fork_acquirer(start, || match PidFile::new(&path) {
+                    Ok(pid_file) => hold(pid_file),
+                    Err(error) if error.kind() == io::ErrorKind::AddrInUse => IN_USE,
+                    Err(_) => FAILED,
+                })
+            })
+            .collect();
+        let (_, codes) = finish_round(round, &path, &children);
+        assert!(
+            codes.iter().all(|code| *code == IN_USE),
+            "round {round}: {codes:?}"
+        );
+    }
+}
+
+#[test]
+fn test_concurrent_stale_reclaim() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("stale.pid");
+
+    for (round, atomic) in [false, true].into_iter().cycle().take(10).enumerate() {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let stale = child.id();
+        child.wait().unwrap();
+        std::fs::write(&path, stale.to_string()).unwrap();
+        let start = Instant::now() + Duration::from_millis(50);
+
+        let children: Vec<_> = (0..ACQUIRERS)
+            .map(|_| {
+                fork_acquirer(start, || {
+                    match Builder::new(&path).atomic_create(atomic).create() {
+                        Ok(pid_file) => hold(pid_file),
+                        Err(error) if error.kind() == io::ErrorKind::AddrInUse => IN_USE,
+                        Err(_) => FAILED,
+                    }
+                })
+            })
+            .collect();
+        let (_, codes) = finish_round(round, &path, &children);
+        assert!(
+            codes.iter().all(|code| *code == IN_USE),
+            "round {round}: {codes:?}"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
+
+#[test]
+fn test_concurrent_new_at() {
+    use std::os::fd::AsFd;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("at.pid");
+
+    for (round, seed_stale) in [false, true].into_iter().cycle().take(10).enumerate() {
+        let _ = std::fs::remove_file(&path);
+        if seed_stale {
+            let mut child = std::process::Command::new("true").spawn().unwrap();
+            std::fs::write(&path, child.id().to_string()).unwrap();
+            child.wait().unwrap();
+        }
+        let start = Instant::now() + Duration::from_millis(50);
+
+        let children: Vec<_> = (0..ACQUIRERS)
+            .map(|_| {
+                fork_acquirer(start, || {
+                    let dir = std::fs::File::open(tmp.path()).unwrap();
+                    match PidFile::new_at(dir.as_fd(), "at.pid") {
+                        Ok(pid_file) => hold(pid_file),
+                        Err(error) if error.kind() == io::ErrorKind::AddrInUse => IN_USE,
+                        Err(_) => FAILED,
+                    }
+                })
+            })
+            .collect();
+        let (_, codes) = finish_round(round, &path, &children);
+        assert!(
+            codes.iter().all(|code| *code == IN_USE),
+            "round {round}: {codes:?}"
+        );
+    }
+}
+
+#[test]
+fn test_concurrent_holder_reported() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("holder.pid");
+
+    for (round, atomic) in [false, true].into_iter().cycle().take(10).enumerate() {
+        let _ = std::fs::remove_file(&path);
+        let start = Instant::now() + Duration::from_millis(50);
+
+        let children: Vec<_> = (0..ACQUIRERS)
+            .map(|index| {
+                let report = tmp.path().join(format!("report-{index}"));
+                fork_acquirer(start, || {
+                    let result = if atomic {
+                        Builder::new(&path).atomic_create(true).create()
+                    } else {
+                        PidFile::new(&path)
+                    };
+                    match result {
+                        Ok(pid_file) => hold(pid_file),
+                        Err(_) => match PidFile::new_result_with_owner(&path) {
+                            Ok(Ok(pid_file)) => hold(pid_file),
+                            Ok(Err(holder)) => {
+                                std::fs::write(report, holder.to_string()).unwrap();
+                                IN_USE
+                            }
+                            Err(_) => FAILED,
+                        },
+                    }
+                })
+            })
+            .collect();
+
+        let (winner, codes) = finish_round(round, &path, &children);
+        assert!(
+            codes.iter().all(|code| *code == IN_USE),
+            "round {round}: {codes:?}"
+        );
+        for index in 0..ACQUIRERS {
+            let report = tmp.path().join(format!("report-{index}"));
+            if let Ok(holder) = std::fs::read_to_string(&report) {
+                assert_eq!(holder, winner.to_string(), "round {round}");
+                std::fs::remove_file(report).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! The `log` bridge only emits records when no `tracing` subscriber has ever been set,
+//! so this runs as its own test binary, away from tests which silence `tracing`.
+#![cfg(feature = "log")]
+
+use std::sync::Mutex;
+
+use pidfile::PidFile;
+
+struct Capture(Mutex<Vec<String>>);
+
+impl log::Log for Capture {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        self.0.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+#[test]
+fn test_log_bridge() {
+    log::set_logger(&CAPTURE).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("pidfile-log.pid");
+    std::fs::write(&path, "not a pid").unwrap();
+    assert!(!PidFile::is_locked(&path).unwrap());
+
+    let needle = format!("Unable to parse PID file {}", path.display());
+    let records = CAPTURE.0.lock().unwrap();
+    assert!(
+        records.iter().any(|record| record.contains(&needle)),
+        "{records:?}"
+    );
+}
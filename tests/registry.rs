@@ -0,0 +1,25 @@
+//! Clearing the registry affects every PID file held by the process, so this runs as its own
+//! test binary, away from tests which rely on the registry.
+#![cfg(feature = "test-util")]
+
+use std::io;
+
+use pidfile::{clear_registry, PidFile};
+
+#[test]
+fn test_clear_registry() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("pidfile-registry.pid");
+
+    let pid_file = PidFile::new(&path).unwrap();
+    let error = PidFile::new(&path).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+
+    clear_registry();
+    std::fs::remove_file(&path).unwrap();
+    let replacement = PidFile::new(&path).unwrap();
+
+    std::mem::forget(pid_file);
+    drop(replacement);
+    assert!(!path.exists());
+}
@@ -0,0 +1,130 @@
+//! Signal handlers are process-wide, and remove every PID file held by the process, so
+//! this runs as its own test binary, away from tests which hold other PID files.
+#![cfg(all(unix, feature = "signal-cleanup"))]
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use pidfile::{PidFile, SignalCleanup};
+
+/// Only one [`SignalCleanup`] can be installed at a time, so these tests take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Whether [`handle`] has received a signal.
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle(_signal: libc::c_int) {
+    HANDLED.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn test_signal_cleanup() {
+    let _serial = SERIAL.lock().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("pidfile-signal-cleanup.pid");
+
+    // SAFETY: Forking to observe the default action, which terminates the child. The child
+    // only creates the PID file and raises the signal before it exits.
+    #[allow(unsafe_code)]
+    let child = unsafe { libc::fork() };
+    assert!(child >= 0, "fork failed");
+    if child == 0 {
+        let _cleanup = SignalCleanup::install().unwrap();
+        let _pid_file = PidFile::new(&path).unwrap();
+        // SAFETY: Raising a signal with a handler installed is always safe.
+        #[allow(unsafe_code)]
+        unsafe {
+            libc::raise(libc::SIGTERM);
+            // The default action terminates the child before this is reached.
+            libc::_exit(0);
+        }
+    }
+
+    let mut status = 0;
+    // SAFETY: `child` is our child, and `status` is valid for the call.
+    #[allow(unsafe_code)]
+    let reaped = unsafe { libc::waitpid(child, &mut status, 0) };
+    assert_eq!(reaped, child);
+    assert!(libc::WIFSIGNALED(status));
+    assert_eq!(libc::WTERMSIG(status), libc::SIGTERM);
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_signal_cleanup_ignored() {
+    let _serial = SERIAL.lock().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("pidfile-signal-cleanup-ignored.pid");
+
+    // SAFETY: Ignoring SIGINT is always safe, and is restored by the guard on drop.
+    #[allow(unsafe_code)]
+    let original = unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+
+    let cleanup = SignalCleanup::install().unwrap();
+    assert_eq!(
+        SignalCleanup::install().unwrap_err().kind(),
+        io::ErrorKind::AlreadyExists
+    );
+
+    let pid_file = PidFile::new(&path).unwrap();
+    assert!(path.exists());
+
+    // The previous disposition ignores the re-raised signal, so the process keeps running,
+    // and still holds its PID file.
+    // SAFETY: Raising a signal with a handler installed is always safe.
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+    assert!(path.exists());
+
+    drop(pid_file);
+    drop(cleanup);
+
+    // SAFETY: Restoring the original disposition of SIGINT.
+    #[allow(unsafe_code)]
+    let restored = unsafe { libc::signal(libc::SIGINT, original) };
+    assert_eq!(restored, libc::SIG_IGN);
+}
+
+#[test]
+fn test_signal_cleanup_chains() {
+    let _serial = SERIAL.lock().unwrap();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("pidfile-signal-cleanup-chains.pid");
+
+    // SAFETY: `handle` only stores to an atomic, and the previous disposition is restored
+    // at the end of the test.
+    #[allow(unsafe_code)]
+    let original = unsafe {
+        libc::signal(
+            libc::SIGINT,
+            handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+
+    let cleanup = SignalCleanup::install().unwrap();
+    let pid_file = PidFile::new(&path).unwrap();
+
+    // The signal is passed on to the previous handler, which keeps the process running, so
+    // the PID file is left alone.
+    // SAFETY: Raising a signal with a handler installed is always safe.
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::raise(libc::SIGINT);
+    }
+    assert!(HANDLED.load(Ordering::SeqCst));
+    assert!(path.exists());
+
+    drop(pid_file);
+    drop(cleanup);
+
+    // SAFETY: Restoring the original disposition of SIGINT.
+    #[allow(unsafe_code)]
+    let restored = unsafe { libc::signal(libc::SIGINT, original) };
+    assert_eq!(
+        restored,
+        handle as extern "C" fn(libc::c_int) as libc::sighandler_t
+    );
+}
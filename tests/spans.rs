@@ -0,0 +1,117 @@
+//! The spans around acquiring a PID file, captured with a minimal `tracing` subscriber.
+#![cfg(all(unix, feature = "tracing"))]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use pidfile::PidFile;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A span which was created, with its fields and the span it was created in.
+#[derive(Debug, Clone)]
+struct Span {
+    id: u64,
+    name: &'static str,
+    fields: Vec<(&'static str, String)>,
+    parent: Option<u64>,
+}
+
+impl Visit for Span {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.fields.push((field.name(), format!("{value:?}")));
+    }
+}
+
+impl Span {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Records every span, and which spans are entered, on a single thread.
+#[derive(Clone, Default)]
+struct Capture {
+    next: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<Span>>>,
+    entered: Arc<Mutex<Vec<u64>>>,
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let parent = match attributes.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if attributes.is_contextual() => self.entered.lock().unwrap().last().copied(),
+            None => None,
+        };
+
+        let mut span = Span {
+            id,
+            name: attributes.metadata().name(),
+            fields: Vec::new(),
+            parent,
+        };
+        attributes.record(&mut span);
+        self.spans.lock().unwrap().push(span);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.entered.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &Id) {
+        self.entered.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn test_acquisition_spans() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("spans.pid");
+
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    let stale = child.id();
+    child.wait().unwrap();
+    std::fs::write(&path, stale.to_string()).unwrap();
+
+    let capture = Capture::default();
+    let pid_file =
+        tracing::subscriber::with_default(capture.clone(), || PidFile::new(&path).unwrap());
+
+    let spans = capture.spans.lock().unwrap();
+    let acquisition = spans
+        .iter()
+        .find(|span| span.name == "pidfile")
+        .unwrap_or_else(|| panic!("no pidfile span in {spans:?}"));
+    assert_eq!(
+        acquisition.field("path"),
+        Some(path.display().to_string().as_str())
+    );
+
+    // The stale owner is checked within the acquisition span.
+    let liveness = spans
+        .iter()
+        .find(|span| span.name == "liveness")
+        .unwrap_or_else(|| panic!("no liveness span in {spans:?}"));
+    assert_eq!(liveness.field("pid"), Some(stale.to_string().as_str()));
+    assert_eq!(liveness.parent, Some(acquisition.id));
+    assert!(capture.entered.lock().unwrap().is_empty());
+    drop(spans);
+    drop(pid_file);
+}
@@ -0,0 +1,27 @@
+//! The `umask` is process-wide, so this runs as its own test binary, away from tests which
+//! create files.
+#![cfg(all(unix, feature = "std"))]
+
+use std::os::unix::fs::PermissionsExt;
+
+use pidfile::PidFile;
+
+#[test]
+fn test_mode_ignores_umask() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("umask.pid");
+
+    // SAFETY: `umask` is always successful.
+    #[allow(unsafe_code)]
+    let previous = unsafe { libc::umask(0o077) };
+    let pid_file = PidFile::new(&path).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+    // SAFETY: As above.
+    #[allow(unsafe_code)]
+    unsafe {
+        libc::umask(previous)
+    };
+
+    assert_eq!(mode, 0o644);
+    drop(pid_file);
+}